@@ -1,30 +1,135 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
 use cosmwasm_std::{
-    entry_point, to_binary, Binary, CanonicalAddr, Deps, DepsMut, Env, MessageInfo, Response,
-    StdError, StdResult,
+    entry_point, from_slice, to_binary, to_vec, Binary, CanonicalAddr, Deps, DepsMut, Env,
+    MessageInfo, QueryRequest, Response, StdError, StdResult, Storage, Uint128, WasmQuery,
 };
 
 use crate::msg::ExecuteAnswer::ViewingKeyResponse;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{config, config_read, State};
+use crate::msg::{
+    AccessCountsResponse, AccessDeniedReason, AccessValidityResponse, AccountKey,
+    AllowedQueriesResponse, AuthModeInfo, AuthModesResponse, AuthorizationCheck, BlockInfoResponse,
+    BulkCheckAccessResponse, CanonicalAddressResponse, CompareViewingKeysResponse,
+    EstimateGasResponse, ExecuteMsg, ExplainAuthorizationResponse, HealthCheckResponse,
+    InstantiateConfig, InstantiateMsg, ListSecretsResponse, ManifestHashResponse,
+    MembershipChangeInfo, MembershipHistoryResponse, OracleQueryAnswer, OracleQueryMsg,
+    OwnerActivityResponse, OwnerDashboardResponse, PendingRemovalsResponse, PlannedViewer,
+    PolicyDocumentResponse, PolicyLimits, PolicyViewer, QueryMsg, QueryWithPermit,
+    RecentActionInfo, RecentActionsResponse, RecoveryHintHashResponse, SchemaVersionResponse,
+    SecretMatchesResponse, SecretReferenceResponse, SecretVariablesHistoryResponse, SelfTestCheck,
+    SelfTestResponse, Snip721QueryAnswer, Snip721QueryMsg, Snip721ViewerInfo,
+    StateFingerprintResponse, StatsResponse, ValidateAccessPlanResponse, ViewerMetadataResponse,
+    ViewersResponse, ViewingKeyOwnerResponse, Voucher,
+};
+use crate::state::{
+    acknowledged_reads, acknowledged_reads_read, config, config_read, failed_auths,
+    failed_auths_read, has_key, has_key_read, last_grant_height, last_grant_height_read,
+    last_owner_activity, last_owner_activity_read, live_viewing_keys, live_viewing_keys_read,
+    membership_log, membership_log_len, membership_log_len_read, membership_log_read,
+    recent_actions, recent_actions_read, redeemed_vouchers, redeemed_vouchers_read, viewer_key_ttl,
+    viewer_key_ttl_read, viewer_metadata, viewer_metadata_read, viewer_min_entropy,
+    viewer_min_entropy_read, viewing_key_expiry, viewing_key_expiry_read, AccessCount,
+    AllowedViewer, DelegationGate, MembershipAction, MembershipChange, NftGate, ReadCosign,
+    ReadQuorum, RecentAction, RevealOracle, ScheduledRemoval, SecretReference, State,
+    PREFIX_REVOKED_PERMITS, SCHEMA_VERSION,
+};
+use secret_toolkit::permit::{validate, Permit, TokenPermissions};
 use secret_toolkit::viewing_key::{ViewingKey, ViewingKeyStore};
 use secret_toolkit_crypto::sha_256;
+use serde::Serialize;
 
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
-    let state = State {
+    let msg = merge_config_json(msg)?;
+    // keys of any other length could never be generated, locking everyone out
+    if let Some(viewing_key_length) = msg.viewing_key_length {
+        if viewing_key_length != VIEWING_KEY_LENGTH {
+            return Err(StdError::generic_err(format!(
+                "viewing_key_length has to be {}, the length of generated keys",
+                VIEWING_KEY_LENGTH
+            )));
+        }
+    }
+    let mut state = State {
         owner: deps.api.addr_canonicalize(info.sender.as_str())?,
+        pending_owner: None,
         allowed_viewers: vec![],
-        secret_variables: "".to_string(),
+        secret_variables: BTreeMap::new(),
+        public_variables: BTreeMap::new(),
+        min_update_interval_blocks: msg.min_update_interval_blocks.unwrap_or(0),
+        last_updated_height: None,
+        schema_version: SCHEMA_VERSION,
+        max_failed_auths: msg.max_failed_auths.unwrap_or(0),
+        lockout_blocks: msg.lockout_blocks.unwrap_or(0),
+        max_total_bytes: msg.max_total_bytes,
+        nft_gate: None,
+        delegation_gate: None,
+        reveal_oracle: None,
+        recovery_hint_hash: None,
+        secret_reference: None,
+        secret_variables_checksum: checksum(&BTreeMap::new())?,
+        paused: false,
+        pause_reason: None,
+        auto_pause_threshold: msg.auto_pause_threshold.unwrap_or(0),
+        auto_pause_window_blocks: msg.auto_pause_window_blocks.unwrap_or(0),
+        recent_failed_auths: vec![],
+        governance: None,
+        pending_viewers: vec![],
+        read_quorum: None,
+        read_cosigns: vec![],
+        staged_secret_variables: None,
+        viewing_key_length: msg.viewing_key_length.unwrap_or(VIEWING_KEY_LENGTH),
+        manifest_hash: None,
+        viewing_key_ttl_blocks: None,
+        read_once_keys: vec![],
+        consumed_keys: vec![],
+        readable_until: BTreeMap::new(),
+        expired_fallbacks: BTreeMap::new(),
+        access_counts: vec![],
+        secret_variables_history: vec![],
+        max_history_len: msg.max_history_len.unwrap_or(0),
+        max_keys_total: msg.max_keys_total,
+        voucher_pubkey: None,
+        disabled_actions: vec![],
+        scheduled_removals: vec![],
+        immutable: false,
     };
 
+    if let Some(contents) = msg.immutable {
+        for viewer in &contents.viewers {
+            let viewer_raw = deps.api.addr_canonicalize(viewer.as_str()).map_err(|_| {
+                StdError::generic_err(format!("Invalid viewer address: {}", viewer))
+            })?;
+            if !state.is_allowed_viewer(&viewer_raw) {
+                record_membership_change(
+                    deps.storage,
+                    &viewer_raw,
+                    MembershipAction::Added,
+                    env.block.height,
+                )?;
+                state.allowed_viewers.push(AllowedViewer {
+                    address: viewer_raw,
+                    keys: None,
+                });
+            }
+        }
+        state.secret_variables_checksum = checksum(&contents.secret_variables)?;
+        state.secret_variables = contents.secret_variables;
+        state.last_updated_height = Some(env.block.height);
+        state.immutable = true;
+        fit_storage_budget(&mut state)?;
+    }
+
     deps.api
         .debug(format!("Contract was initialized by {}", info.sender).as_str());
     config(deps.storage).save(&state)?;
+    last_owner_activity(deps.storage).save(&env.block.height)?;
 
     let prng_seed_hashed = sha_256(&msg.prng_seed.0);
     ViewingKey::set_seed(deps.storage, &prng_seed_hashed);
@@ -32,16 +137,216 @@ pub fn instantiate(
     Ok(Response::default())
 }
 
+/// Moves the limits in `msg.config_json` into `msg`, rejecting unknown fields
+/// and limits that are also set directly in the message
+fn merge_config_json(mut msg: InstantiateMsg) -> StdResult<InstantiateMsg> {
+    fn merge<T>(name: &str, field: &mut Option<T>, value: Option<T>) -> StdResult<()> {
+        if value.is_some() {
+            if field.is_some() {
+                return Err(StdError::generic_err(format!(
+                    "{} is set both in the message and in config_json",
+                    name
+                )));
+            }
+            *field = value;
+        }
+        Ok(())
+    }
+
+    let config_json = match msg.config_json.take() {
+        Some(config_json) => config_json,
+        None => return Ok(msg),
+    };
+    let config: InstantiateConfig = from_slice(config_json.as_bytes())
+        .map_err(|e| StdError::generic_err(format!("Invalid config_json: {}", e)))?;
+
+    merge(
+        "min_update_interval_blocks",
+        &mut msg.min_update_interval_blocks,
+        config.min_update_interval_blocks,
+    )?;
+    merge(
+        "max_failed_auths",
+        &mut msg.max_failed_auths,
+        config.max_failed_auths,
+    )?;
+    merge(
+        "lockout_blocks",
+        &mut msg.lockout_blocks,
+        config.lockout_blocks,
+    )?;
+    merge(
+        "max_total_bytes",
+        &mut msg.max_total_bytes,
+        config.max_total_bytes,
+    )?;
+    merge(
+        "auto_pause_threshold",
+        &mut msg.auto_pause_threshold,
+        config.auto_pause_threshold,
+    )?;
+    merge(
+        "auto_pause_window_blocks",
+        &mut msg.auto_pause_window_blocks,
+        config.auto_pause_window_blocks,
+    )?;
+    merge(
+        "viewing_key_length",
+        &mut msg.viewing_key_length,
+        config.viewing_key_length,
+    )?;
+    merge(
+        "max_history_len",
+        &mut msg.max_history_len,
+        config.max_history_len,
+    )?;
+    merge(
+        "max_keys_total",
+        &mut msg.max_keys_total,
+        config.max_keys_total,
+    )?;
+
+    Ok(msg)
+}
+
 #[entry_point]
 pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+    let state = config_read(deps.storage).load()?;
+    let action = action_name(&msg);
+    if state.disabled_actions.iter().any(|a| a == action) {
+        return Err(StdError::generic_err(format!(
+            "{} has been disabled",
+            action
+        )));
+    }
+
+    // a failing message reverts this together with everything else, and
+    // SelfTest leaves storage untouched altogether
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if state.immutable && sender_address_raw == state.owner {
+        return Err(StdError::generic_err(
+            "The contract is immutable, its owner can no longer execute messages",
+        ));
+    }
+    if !matches!(msg, ExecuteMsg::SelfTest {}) {
+        if sender_address_raw == state.owner {
+            last_owner_activity(deps.storage).save(&env.block.height)?;
+        }
+        record_recent_action(deps.storage, action, sender_address_raw, env.block.height)?;
+    }
+
     match msg {
-        ExecuteMsg::SetViewers { viewers } => try_set_viewers(deps, info, viewers),
-        ExecuteMsg::SetSecretVariables { secret_variables } => {
-            try_set_secret_variables(deps, info, secret_variables)
+        ExecuteMsg::SetViewers {
+            viewers,
+            skip_invalid,
+        } => try_set_viewers(deps, env, info, viewers, skip_invalid.unwrap_or(false)),
+        ExecuteMsg::SetSecretVariables {
+            secret_variables,
+            trim,
+        } => try_set_secret_variables(deps, env, info, secret_variables, trim.unwrap_or(false)),
+        ExecuteMsg::InitSecretVariables { secret_variables } => {
+            try_init_secret_variables(deps, env, info, secret_variables)
+        }
+        ExecuteMsg::SetSecretVariable { key, value } => {
+            try_set_secret_variable(deps, env, info, key, value)
+        }
+        ExecuteMsg::RemoveSecretVariable { key } => {
+            try_remove_secret_variable(deps, env, info, key)
+        }
+        ExecuteMsg::SetReadOnce { key, read_once } => try_set_read_once(deps, info, key, read_once),
+        ExecuteMsg::SetReadableUntil {
+            key,
+            readable_until,
+            fallback,
+        } => try_set_readable_until(deps, info, key, readable_until, fallback),
+        ExecuteMsg::AcknowledgeRead { key } => try_acknowledge_read(deps, env, info, key),
+        ExecuteMsg::RollbackSecretVariables { steps } => {
+            try_rollback_secret_variables(deps, env, info, steps)
+        }
+        ExecuteMsg::SetVoucherPubkey { pubkey } => try_set_voucher_pubkey(deps, info, pubkey),
+        ExecuteMsg::DisableActions { actions } => try_disable_actions(deps, info, actions),
+        ExecuteMsg::SelfTest {} => try_self_test(deps, info),
+        ExecuteMsg::RedeemVoucher { voucher, signature } => {
+            try_redeem_voucher(deps, env, info, voucher, signature)
         }
         ExecuteMsg::GenerateViewingKey { entropy } => {
             try_generate_viewing_key(deps, info, env, entropy)
         }
+        ExecuteMsg::SetPublicVariables { public_variables } => {
+            try_set_public_variables(deps, info, public_variables)
+        }
+        ExecuteMsg::ReportFailedAuth {
+            account,
+            viewing_key,
+        } => try_report_failed_auth(deps, env, info, account, viewing_key),
+        ExecuteMsg::ImportViewersCsv { csv, skip_invalid } => {
+            try_import_viewers_csv(deps, env, info, csv, skip_invalid.unwrap_or(false))
+        }
+        ExecuteMsg::SetNftGate { nft_gate } => try_set_nft_gate(deps, info, nft_gate),
+        ExecuteMsg::SetDelegationGate { delegation_gate } => {
+            try_set_delegation_gate(deps, info, delegation_gate)
+        }
+        ExecuteMsg::SetRevealOracle { reveal_oracle } => {
+            try_set_reveal_oracle(deps, info, reveal_oracle)
+        }
+        ExecuteMsg::SetSecretReference { uri, hash } => {
+            try_set_secret_reference(deps, info, uri, hash)
+        }
+        ExecuteMsg::Pause { reason } => try_pause(deps, info, reason),
+        ExecuteMsg::Unpause {} => try_unpause(deps, info),
+        ExecuteMsg::SetGovernance { governance } => try_set_governance(deps, info, governance),
+        ExecuteMsg::AddViewer {
+            viewer,
+            viewing_key_ttl_blocks,
+        } => try_add_viewer(deps, env, info, viewer, viewing_key_ttl_blocks),
+        ExecuteMsg::OnboardViewer {
+            viewer,
+            keys,
+            entropy,
+        } => try_onboard_viewer(deps, env, info, viewer, keys, entropy),
+        ExecuteMsg::LeaveAsViewer {} => try_leave_as_viewer(deps, env, info),
+        ExecuteMsg::SetReadQuorum { read_quorum } => try_set_read_quorum(deps, info, read_quorum),
+        ExecuteMsg::SetViewingKeyTtl {
+            viewing_key_ttl_blocks,
+        } => try_set_viewing_key_ttl(deps, info, viewing_key_ttl_blocks),
+        ExecuteMsg::CosignRead { viewing_key } => try_cosign_read(deps, env, info, viewing_key),
+        ExecuteMsg::GetSecretVariablesAudited { viewing_key, keys } => {
+            try_get_secret_variables_audited(deps, env, info, viewing_key, keys)
+        }
+        ExecuteMsg::SetViewerMetadata { viewer, metadata } => {
+            try_set_viewer_metadata(deps, info, viewer, metadata)
+        }
+        ExecuteMsg::SetViewerMinEntropy {
+            viewer,
+            min_entropy_length,
+        } => try_set_viewer_min_entropy(deps, info, viewer, min_entropy_length),
+        ExecuteMsg::SetViewerScope { viewer, keys } => {
+            try_set_viewer_scope(deps, env, info, viewer, keys)
+        }
+        ExecuteMsg::StageSecrets { secret_variables } => {
+            try_stage_secrets(deps, info, secret_variables)
+        }
+        ExecuteMsg::CommitStaged {} => try_commit_staged(deps, env, info),
+        ExecuteMsg::DiscardStaged {} => try_discard_staged(deps, info),
+        ExecuteMsg::SetManifestHash { manifest_hash } => {
+            try_set_manifest_hash(deps, info, manifest_hash)
+        }
+        ExecuteMsg::RevokeViewingKeysFor { viewers } => {
+            try_revoke_viewing_keys_for(deps, env, info, viewers)
+        }
+        ExecuteMsg::RevokeViewer { viewer } => try_revoke_viewer(deps, env, info, viewer),
+        ExecuteMsg::ScheduleViewerRemoval {
+            viewer,
+            remove_at_height,
+        } => try_schedule_viewer_removal(deps, env, info, viewer, remove_at_height),
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            try_transfer_ownership(deps, info, new_owner)
+        }
+        ExecuteMsg::AcceptOwnership {} => try_accept_ownership(deps, env, info),
+        ExecuteMsg::ApproveViewer { viewer } => try_approve_viewer(deps, env, info, viewer),
+        ExecuteMsg::SetRecoveryHintHash { recovery_hint_hash } => {
+            try_set_recovery_hint_hash(deps, info, recovery_hint_hash)
+        }
     }
 }
 
@@ -54,241 +359,9064 @@ pub fn try_generate_viewing_key(
     let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
     let state = config_read(deps.storage).load()?;
 
-    if !state.allowed_viewers.contains(&sender_address_raw) {
-        return Err(StdError::generic_err(
-            "Only allowed viewers can generate viewing keys",
-        ));
+    // the owner needs a key of its own for owner-only queries
+    if sender_address_raw != state.owner {
+        if state.allowed_viewers.is_empty() {
+            return Err(StdError::generic_err(
+                "No viewers have been set, viewing keys cannot be generated yet",
+            ));
+        }
+
+        if !state.is_allowed_viewer(&sender_address_raw) {
+            return Err(StdError::generic_err(
+                "Only allowed viewers can generate viewing keys",
+            ));
+        }
+
+        check_viewer_entropy(deps.storage, &sender_address_raw, &entropy)?;
     }
 
-    let key = ViewingKey::create(
+    // owner keys never expire
+    let ttl = if sender_address_raw == state.owner {
+        None
+    } else {
+        viewer_key_ttl_blocks(deps.storage, &state, &sender_address_raw)?
+    };
+    let key = create_viewing_key(
         deps.storage,
         &info,
         &env,
         info.sender.as_str(),
-        entropy.as_ref(),
-    );
+        &sender_address_raw,
+        &entropy,
+        ttl,
+    )?;
 
     Ok(Response::new().set_data(to_binary(&ViewingKeyResponse { key })?))
 }
 
+/// Rejects `entropy` shorter than the minimum the owner set for `viewer_raw`
+fn check_viewer_entropy(
+    storage: &dyn Storage,
+    viewer_raw: &CanonicalAddr,
+    entropy: &str,
+) -> StdResult<()> {
+    let min_entropy_length = viewer_min_entropy_read(storage).may_load(viewer_raw.as_slice())?;
+    if let Some(min_entropy_length) = min_entropy_length {
+        if entropy.len() < min_entropy_length as usize {
+            return Err(StdError::generic_err(format!(
+                "Entropy has to be at least {} bytes long for this viewer",
+                min_entropy_length
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// TTL of viewing keys generated for `viewer_raw`, its own before the default
+fn viewer_key_ttl_blocks(
+    storage: &dyn Storage,
+    state: &State,
+    viewer_raw: &CanonicalAddr,
+) -> StdResult<Option<u64>> {
+    Ok(viewer_key_ttl_read(storage)
+        .may_load(viewer_raw.as_slice())?
+        .or(state.viewing_key_ttl_blocks))
+}
+
+/// Creates a new viewing key for `account`, which expires `ttl` blocks from now if given.
+/// Replacing an existing key does not count towards `max_keys_total`, and the owner
+/// is never refused a key so it cannot lock itself out of owner-only queries
+fn create_viewing_key(
+    storage: &mut dyn Storage,
+    info: &MessageInfo,
+    env: &Env,
+    account: &str,
+    account_raw: &CanonicalAddr,
+    entropy: &str,
+    ttl: Option<u64>,
+) -> StdResult<String> {
+    if has_key_read(storage)
+        .may_load(account_raw.as_slice())?
+        .is_none()
+    {
+        let live_keys = live_viewing_keys_read(storage).may_load()?.unwrap_or(0);
+        let state = config_read(storage).load()?;
+        if let Some(max_keys_total) = state.max_keys_total {
+            if live_keys >= max_keys_total && *account_raw != state.owner {
+                return Err(StdError::generic_err(format!(
+                    "The limit of {} viewing keys has been reached",
+                    max_keys_total
+                )));
+            }
+        }
+        live_viewing_keys(storage).save(&(live_keys + 1))?;
+    }
+
+    #[cfg(not(feature = "deterministic-keys"))]
+    let key = ViewingKey::create(storage, info, env, account, entropy.as_ref());
+    #[cfg(feature = "deterministic-keys")]
+    let key = {
+        // `info` only seeds random keys
+        let _ = info;
+        let key = deterministic_viewing_key(account, entropy);
+        ViewingKey::set(storage, account, &key);
+        key
+    };
+    has_key(storage).save(account_raw.as_slice(), &true)?;
+
+    match ttl {
+        Some(ttl) => {
+            viewing_key_expiry(storage).save(account_raw.as_slice(), &(env.block.height + ttl))?
+        }
+        None => viewing_key_expiry(storage).remove(account_raw.as_slice()),
+    }
+
+    Ok(key)
+}
+
+/// Test-only key derivation that depends on nothing but the account and the entropy
+#[cfg(feature = "deterministic-keys")]
+fn deterministic_viewing_key(account: &str, entropy: &str) -> String {
+    let hash = sha_256(&[account.as_bytes(), entropy.as_bytes()].concat());
+    format!("api_key_{}", Binary::from(hash.to_vec()).to_base64())
+}
+
+/// Replaces all secret variables at once, optionally trimming surrounding whitespace from each value
 pub fn try_set_secret_variables(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
-    secret_variables: String,
+    mut secret_variables: BTreeMap<String, String>,
+    trim: bool,
 ) -> StdResult<Response> {
-    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
-    let state = config_read(deps.storage).load()?;
-
-    if sender_address_raw != state.owner {
-        return Err(StdError::generic_err(
-            "Only the owner can set secret variables",
-        ));
+    if trim {
+        for value in secret_variables.values_mut() {
+            *value = value.trim().to_string();
+        }
     }
+    update_secret_variables(deps, env, info, |current| {
+        *current = secret_variables;
+        Ok(())
+    })
+}
+
+/// Sets a single secret variable, overwriting any previous value of `key`
+pub fn try_set_secret_variable(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    key: String,
+    value: String,
+) -> StdResult<Response> {
+    update_secret_variables(deps, env, info, |current| {
+        current.insert(key, value);
+        Ok(())
+    })
+}
+
+pub fn try_remove_secret_variable(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    key: String,
+) -> StdResult<Response> {
+    update_secret_variables(deps, env, info, |current| {
+        current
+            .remove(&key)
+            .map(|_| ())
+            .ok_or_else(|| StdError::generic_err(format!("No secret variable named {}", key)))
+    })
+}
+
+/// Flags `key` so it is deleted once a viewer acknowledges reading it
+pub fn try_set_read_once(
+    deps: DepsMut,
+    info: MessageInfo,
+    key: String,
+    read_once: bool,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
 
     config(deps.storage).update(|mut state| -> Result<_, StdError> {
-        state.secret_variables = secret_variables;
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set secret variables",
+            ));
+        }
+
+        if !state.secret_variables.contains_key(&key) {
+            return Err(StdError::generic_err(format!(
+                "No secret variable named {}",
+                key
+            )));
+        }
+
+        state.read_once_keys.retain(|k| *k != key);
+        if read_once {
+            state.read_once_keys.push(key);
+        }
         Ok(state)
     })?;
 
-    deps.api.debug("secret variables set successfully");
+    deps.api.debug("read once flag set successfully");
     Ok(Response::default())
 }
 
-pub fn try_set_viewers(
+/// Stops `key` from being returned to viewers from `readable_until` on, the
+/// owner can still list, overwrite and remove it. From then on viewers get
+/// `fallback` instead, if given, or nothing at all
+pub fn try_set_readable_until(
     deps: DepsMut,
     info: MessageInfo,
-    viewers: Vec<String>,
+    key: String,
+    readable_until: Option<u64>,
+    fallback: Option<String>,
 ) -> StdResult<Response> {
     let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
-    let temp_allowed_viewers: Vec<CanonicalAddr> = viewers
-        .iter()
-        .map(|v| deps.api.addr_canonicalize(v.as_str()).unwrap())
-        .collect();
 
     config(deps.storage).update(|mut state| -> Result<_, StdError> {
         if sender_address_raw != state.owner {
-            return Err(StdError::generic_err("Only the owner can set viewers"));
+            return Err(StdError::generic_err(
+                "Only the owner can set secret variables",
+            ));
         }
 
-        state.allowed_viewers = temp_allowed_viewers;
+        if !state.secret_variables.contains_key(&key) {
+            return Err(StdError::generic_err(format!(
+                "No secret variable named {}",
+                key
+            )));
+        }
 
+        match fallback {
+            Some(fallback) if readable_until.is_some() => {
+                state.expired_fallbacks.insert(key.clone(), fallback)
+            }
+            Some(_) => {
+                return Err(StdError::generic_err(
+                    "A fallback needs a readable until height",
+                ))
+            }
+            None => state.expired_fallbacks.remove(&key),
+        };
+        match readable_until {
+            Some(height) => state.readable_until.insert(key, height),
+            None => state.readable_until.remove(&key),
+        };
+        fit_storage_budget(&mut state)?;
         Ok(state)
     })?;
 
-    deps.api.debug("viewers set successfully");
     Ok(Response::default())
 }
 
-#[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetSecretVariables {
-            viewing_key,
-            account,
-        } => to_binary(&query_secret_variables(deps, viewing_key, account)?),
-    }
+/// Called by a viewer after reading a read once secret, deletes it so it
+/// cannot be read again. A viewer past its scheduled removal can no longer
+/// acknowledge, and nobody can while reads are paused
+pub fn try_acknowledge_read(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    key: String,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        check_not_paused(&state)?;
+        let viewer = state
+            .allowed_viewers
+            .iter()
+            .find(|v| v.address == sender_address_raw)
+            .filter(|_| state.is_active_viewer(&sender_address_raw, env.block.height))
+            .ok_or_else(|| StdError::generic_err("Only allowed viewers can acknowledge reads"))?;
+        if matches!(&viewer.keys, Some(keys) if !keys.contains(&key)) {
+            return Err(StdError::generic_err(format!(
+                "{} is outside of the viewer's scope",
+                key
+            )));
+        }
+
+        let position = state
+            .read_once_keys
+            .iter()
+            .position(|k| *k == key)
+            .ok_or_else(|| StdError::generic_err(format!("{} is not a read once secret", key)))?;
+        state.read_once_keys.remove(position);
+        state.secret_variables.remove(&key);
+        state.secret_variables_checksum = checksum(&state.secret_variables)?;
+        state.consumed_keys.push(key);
+        Ok(state)
+    })?;
+
+    let count = acknowledged_reads_read(deps.storage)
+        .may_load()?
+        .unwrap_or(0);
+    acknowledged_reads(deps.storage).save(&(count + 1))?;
+
+    deps.api.debug("read acknowledged successfully");
+    Ok(Response::default())
 }
 
-fn query_secret_variables(deps: Deps, viewing_key: String, account: String) -> StdResult<String> {
+/// Restores the secret variables from `steps` versions back. The replaced
+/// variables go into the history like any other update, so a rollback can be undone
+pub fn try_rollback_secret_variables(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    steps: u32,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
     let state = config_read(deps.storage).load()?;
-    let result = ViewingKey::check(deps.storage, account.as_ref(), viewing_key.as_ref());
 
-    if !result.is_ok() {
+    if sender_address_raw != state.owner {
         return Err(StdError::generic_err(
-            "Only allowed viewers can query secret variables",
+            "Only the owner can set secret variables",
         ));
     }
 
-    Ok(state.secret_variables)
-}
+    let history_len = state.secret_variables_history.len();
+    if steps == 0 || steps as usize > history_len {
+        return Err(StdError::generic_err(format!(
+            "Cannot roll back {} steps, the history holds {} versions",
+            steps, history_len
+        )));
+    }
+    let (_, secret_variables) =
+        state.secret_variables_history[history_len - steps as usize].clone();
 
-#[cfg(test)]
-mod tests {
+    update_secret_variables(deps, env, info, |current| {
+        *current = secret_variables;
+        Ok(())
+    })
+}
 
-    use super::*;
-    use crate::msg::ExecuteAnswer;
-    use cosmwasm_std::testing::*;
-    use cosmwasm_std::{from_binary, Coin, Uint128};
+/// Appends `previous` to the history, dropping the oldest versions beyond `max_history_len`
+fn record_secret_variables_history(
+    state: &mut State,
+    height: u64,
+    previous: BTreeMap<String, String>,
+) {
+    state.secret_variables_history.push((height, previous));
+    let excess = state
+        .secret_variables_history
+        .len()
+        .saturating_sub(state.max_history_len as usize);
+    state.secret_variables_history.drain(..excess);
+}
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies();
-        let info = mock_info("creator", &[]);
-        let init_msg = InstantiateMsg {
-            prng_seed: b"prng_seed".to_vec().into(),
-        };
+/// Keeps the read once flags and readable until heights in line with the
+/// stored secret variables, a key that is written again after being consumed
+/// can be read again
+fn prune_read_once_keys(state: &mut State) {
+    let secret_variables = &state.secret_variables;
+    state
+        .read_once_keys
+        .retain(|k| secret_variables.contains_key(k));
+    state
+        .consumed_keys
+        .retain(|k| !secret_variables.contains_key(k));
+    state
+        .readable_until
+        .retain(|k, _| secret_variables.contains_key(k));
+    state
+        .expired_fallbacks
+        .retain(|k, _| secret_variables.contains_key(k));
+}
 
-        // we can just call .unwrap() to assert this was a success
-        let res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+/// Applies `update` to the secret variables after the owner and rate limit checks
+fn update_secret_variables<F>(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    update: F,
+) -> StdResult<Response>
+where
+    F: FnOnce(&mut BTreeMap<String, String>) -> StdResult<()>,
+{
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
 
-        assert_eq!(0, res.messages.len())
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can set secret variables",
+        ));
     }
 
-    #[test]
-    fn set_viewers() {
-        let mut deps = mock_dependencies_with_balance(&[Coin {
-            denom: "token".to_string(),
-            amount: Uint128::new(2),
-        }]);
-        let info = mock_info("creator", &[]);
-        let init_msg = InstantiateMsg {
-            prng_seed: b"prng_seed".to_vec().into(),
-        };
-
-        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+    if let Some(last_updated_height) = state.last_updated_height {
+        let next_allowed_height = last_updated_height + state.min_update_interval_blocks;
+        if env.block.height < next_allowed_height {
+            return Err(StdError::generic_err(format!(
+                "Secret variables were updated too recently, try again at height {}",
+                next_allowed_height
+            )));
+        }
+    }
 
-        let info = mock_info("creator", &[]);
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        let previous = state.secret_variables.clone();
+        update(&mut state.secret_variables)?;
+        record_secret_variables_history(&mut state, env.block.height, previous);
+        state.secret_variables_checksum = checksum(&state.secret_variables)?;
+        state.secret_reference = None;
+        prune_read_once_keys(&mut state);
+        state.last_updated_height = Some(env.block.height);
+        fit_storage_budget(&mut state)?;
+        Ok(state)
+    })?;
 
-        let exec_msg = ExecuteMsg::SetViewers {
-            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
-        };
-        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+    deps.api.debug("secret variables set successfully");
+    Ok(Response::default())
+}
 
-        let info = mock_info("anyone", &[]);
+/// Sets the secret variables only if none are stored yet, so setup scripts cannot overwrite them
+pub fn try_init_secret_variables(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    secret_variables: BTreeMap<String, String>,
+) -> StdResult<Response> {
+    let state = config_read(deps.storage).load()?;
 
-        let exec_msg = ExecuteMsg::SetViewers {
-            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
-        };
-        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Anyone cannot set viewers");
+    if !state.secret_variables.is_empty() || state.secret_reference.is_some() {
+        return Err(StdError::generic_err(
+            "Secret variables are already set, use SetSecretVariables to replace them",
+        ));
     }
 
-    #[test]
-    fn proper_generate_vk() {
-        let mut deps = mock_dependencies_with_balance(&[Coin {
-            denom: "token".to_string(),
-            amount: Uint128::new(2),
-        }]);
-        let info = mock_info("creator", &[]);
-        let init_msg = InstantiateMsg {
-            prng_seed: b"prng_seed".to_vec().into(),
-        };
+    try_set_secret_variables(deps, env, info, secret_variables, false)
+}
 
-        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+/// Writes the next secret variables to the staging area, replacing anything staged before
+pub fn try_stage_secrets(
+    deps: DepsMut,
+    info: MessageInfo,
+    secret_variables: BTreeMap<String, String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
 
-        let info = mock_info("creator", &[]);
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set secret variables",
+            ));
+        }
 
-        let exec_msg = ExecuteMsg::SetViewers {
-            viewers: vec!["viewer1".to_string()],
-        };
-        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        state.staged_secret_variables = Some(secret_variables);
+        fit_storage_budget(&mut state)?;
+        Ok(state)
+    })?;
 
-        let info = mock_info("viewer1", &[]);
+    deps.api.debug("secret variables staged successfully");
+    Ok(Response::default())
+}
 
-        let exec_msg = ExecuteMsg::GenerateViewingKey {
-            entropy: "entropy".to_string(),
-        };
-        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+/// Swaps the staged secret variables in, with the same checks as `SetSecretVariables`
+pub fn try_commit_staged(deps: DepsMut, env: Env, info: MessageInfo) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
 
-        let _key: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can set secret variables",
+        ));
+    }
 
-        let info = mock_info("hacker", &[]);
+    let staged = state
+        .staged_secret_variables
+        .take()
+        .ok_or_else(|| StdError::generic_err("No secret variables are staged"))?;
+    config(deps.storage).save(&state)?;
 
-        let exec_msg = ExecuteMsg::GenerateViewingKey {
-            entropy: "entropy".to_string(),
-        };
+    try_set_secret_variables(deps, env, info, staged, false)
+}
 
-        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg)
-            .expect_err("Hacker cannot generate viewing key");
-    }
+pub fn try_discard_staged(deps: DepsMut, info: MessageInfo) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set secret variables",
+            ));
+        }
+
+        state.staged_secret_variables = None;
+        Ok(state)
+    })?;
+
+    deps.api
+        .debug("staged secret variables discarded successfully");
+    Ok(Response::default())
+}
+
+/// Replaces the secret variables with a pointer to content stored off-chain.
+///
+/// `hash` lets viewers check the integrity of whatever they fetch from `uri`.
+pub fn try_set_secret_reference(
+    deps: DepsMut,
+    info: MessageInfo,
+    uri: String,
+    hash: Binary,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set secret variables",
+            ));
+        }
+
+        state.secret_variables = BTreeMap::new();
+        state.secret_variables_checksum = checksum(&state.secret_variables)?;
+        state.secret_reference = Some(SecretReference { uri, hash });
+        prune_read_once_keys(&mut state);
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("secret reference set successfully");
+    Ok(Response::default())
+}
+
+pub fn try_set_public_variables(
+    deps: DepsMut,
+    info: MessageInfo,
+    public_variables: BTreeMap<String, String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can set public variables",
+        ));
+    }
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        state.public_variables = public_variables;
+        Ok(state)
+    })?;
+
+    deps.api.debug("public variables set successfully");
+    Ok(Response::default())
+}
+
+/// Replaces the list of allowed viewers.
+///
+/// Viewing keys are stored per account and are not touched here: a viewer that
+/// stays in the list keeps its key, a removed viewer keeps a key that no longer
+/// grants access because queries also check membership, and a viewer that is
+/// added back can keep using the key it generated before, until it expires. Its
+/// own TTL, minimum entropy and metadata are dropped with it.
+///
+/// With `skip_invalid` set, addresses that fail to canonicalize are left out of
+/// the new list and reported in the `invalid` attribute instead of rejecting
+/// the whole message.
+pub fn try_set_viewers(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewers: Vec<String>,
+    skip_invalid: bool,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err("Only the owner can set viewers"));
+    }
+
+    let mut temp_allowed_viewers = vec![];
+    let mut invalid = vec![];
+    for v in &viewers {
+        match deps.api.addr_canonicalize(v.as_str()) {
+            Ok(viewer) => temp_allowed_viewers.push(viewer),
+            Err(_) if skip_invalid => invalid.push(v.as_str()),
+            Err(_) => {
+                return Err(StdError::generic_err(format!(
+                    "Invalid viewer address: {:?}",
+                    v
+                )))
+            }
+        }
+    }
+
+    // removing viewers needs no approval, adding them does
+    if state.governance.is_some()
+        && temp_allowed_viewers
+            .iter()
+            .any(|viewer| !state.is_allowed_viewer(viewer))
+    {
+        return Err(StdError::generic_err(
+            "Viewers wait for governance approval, use AddViewer instead",
+        ));
+    }
+
+    for viewer in &state.allowed_viewers {
+        if !temp_allowed_viewers.contains(&viewer.address) {
+            record_membership_change(
+                deps.storage,
+                &viewer.address,
+                MembershipAction::Removed,
+                env.block.height,
+            )?;
+            forget_viewer_settings(deps.storage, &viewer.address);
+        }
+    }
+    for viewer in &temp_allowed_viewers {
+        if !state.is_allowed_viewer(viewer) {
+            record_membership_change(
+                deps.storage,
+                viewer,
+                MembershipAction::Added,
+                env.block.height,
+            )?;
+        }
+    }
+
+    // viewers that stay keep their scope, new ones can read every key
+    let allowed_viewers = temp_allowed_viewers
+        .into_iter()
+        .map(|address| AllowedViewer {
+            keys: state
+                .allowed_viewers
+                .iter()
+                .find(|v| v.address == address)
+                .and_then(|v| v.keys.clone()),
+            address,
+        })
+        .collect();
+    state.allowed_viewers = allowed_viewers;
+    prune_access_counts(&mut state);
+    fit_storage_budget(&mut state)?;
+    config(deps.storage).save(&state)?;
+
+    deps.api.debug("viewers set successfully");
+    let mut response = Response::default();
+    if skip_invalid {
+        response = response.add_attribute("invalid", invalid.join(","));
+    }
+    Ok(response)
+}
+
+/// Adds the comma or newline separated addresses in `csv` to the allowed viewers.
+///
+/// Addresses that are already viewers, or repeated in the input, are skipped.
+/// Any address that fails to canonicalize aborts the whole import, unless
+/// `skip_invalid` is set, in which case it is reported in the `invalid`
+/// attribute and the rest are still imported.
+pub fn try_import_viewers_csv(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    csv: String,
+    skip_invalid: bool,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err("Only the owner can set viewers"));
+    }
+
+    let mut added = 0u32;
+    let mut skipped = 0u32;
+    let mut invalid = vec![];
+    for address in csv
+        .split(&[',', '\n'][..])
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+    {
+        let viewer = match deps.api.addr_canonicalize(address) {
+            Ok(viewer) => viewer,
+            Err(_) if skip_invalid => {
+                invalid.push(address);
+                continue;
+            }
+            Err(_) => {
+                return Err(StdError::generic_err(format!(
+                    "Invalid viewer address: {}",
+                    address
+                )))
+            }
+        };
+
+        if state.is_allowed_viewer(&viewer) {
+            skipped += 1;
+        } else if state.governance.is_some() {
+            return Err(StdError::generic_err(
+                "Viewers wait for governance approval, use AddViewer instead",
+            ));
+        } else {
+            record_membership_change(
+                deps.storage,
+                &viewer,
+                MembershipAction::Added,
+                env.block.height,
+            )?;
+            state.allowed_viewers.push(AllowedViewer {
+                address: viewer,
+                keys: None,
+            });
+            added += 1;
+        }
+    }
+
+    fit_storage_budget(&mut state)?;
+    config(deps.storage).save(&state)?;
+
+    deps.api.debug("viewers imported successfully");
+    let mut response = Response::new()
+        .add_attribute("added", added.to_string())
+        .add_attribute("skipped", skipped.to_string());
+    if skip_invalid {
+        response = response.add_attribute("invalid", invalid.join(","));
+    }
+    Ok(response)
+}
+
+/// Offers ownership to `new_owner`, replacing any earlier offer.
+///
+/// Nothing changes until `new_owner` accepts, so a typo cannot lock the owner out.
+pub fn try_transfer_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    new_owner: String,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can transfer ownership",
+        ));
+    }
+
+    state.pending_owner = Some(deps.api.addr_canonicalize(new_owner.as_str())?);
+    config(deps.storage).save(&state)?;
+
+    deps.api.debug("ownership transfer started successfully");
+    Ok(Response::default())
+}
+
+/// Makes the pending owner the owner. The previous owner's viewing key is
+/// invalidated so it no longer takes a `max_keys_total` slot
+pub fn try_accept_ownership(deps: DepsMut, env: Env, info: MessageInfo) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    match &state.pending_owner {
+        None => return Err(StdError::generic_err("No ownership transfer is pending")),
+        Some(pending_owner) if *pending_owner != sender_address_raw => {
+            return Err(StdError::generic_err(
+                "Only the pending owner can accept ownership",
+            ))
+        }
+        Some(_) => {}
+    }
+    let previous_owner = std::mem::replace(&mut state.owner, sender_address_raw);
+    state.pending_owner = None;
+    config(deps.storage).save(&state)?;
+
+    let previous_owner_address = deps.api.addr_humanize(&previous_owner)?;
+    invalidate_viewing_key(
+        deps.storage,
+        &info,
+        &env,
+        previous_owner_address.as_str(),
+        &previous_owner,
+    )?;
+
+    deps.api.debug("ownership accepted successfully");
+    Ok(Response::default())
+}
+
+pub fn try_set_governance(
+    deps: DepsMut,
+    info: MessageInfo,
+    governance: Option<String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let governance = governance
+        .map(|g| deps.api.addr_canonicalize(g.as_str()))
+        .transpose()?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err("Only the owner can set governance"));
+        }
+
+        state.governance = governance;
+        if state.governance.is_none() {
+            state.pending_viewers.clear();
+        }
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("governance set successfully");
+    Ok(Response::default())
+}
+
+/// Adds a single viewer, or queues it for approval when a governance contract is configured
+pub fn try_add_viewer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewer: String,
+    viewing_key_ttl_blocks: Option<u64>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err("Only the owner can set viewers"));
+    }
+    check_viewing_key_ttl(viewing_key_ttl_blocks)?;
+
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    let status = add_viewer(
+        deps.storage,
+        &mut state,
+        &viewer,
+        &viewer_raw,
+        env.block.height,
+    )?;
+
+    config(deps.storage).save(&state)?;
+    match viewing_key_ttl_blocks {
+        Some(ttl) => viewer_key_ttl(deps.storage).save(viewer_raw.as_slice(), &ttl)?,
+        None => viewer_key_ttl(deps.storage).remove(viewer_raw.as_slice()),
+    }
+
+    Ok(Response::new().add_attribute("status", status))
+}
+
+/// Adds `viewer` scoped to `keys`, which cannot be empty, in one go. With
+/// `entropy` its viewing key is generated too and returned to the owner, who
+/// hands it over off-chain. The key follows the viewer's own TTL and minimum
+/// entropy like a self-generated one
+pub fn try_onboard_viewer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewer: String,
+    keys: Vec<String>,
+    entropy: Option<String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err("Only the owner can set viewers"));
+    }
+    if state.governance.is_some() {
+        return Err(StdError::generic_err(
+            "Viewers wait for governance approval, use AddViewer instead",
+        ));
+    }
+
+    if keys.is_empty() {
+        return Err(StdError::generic_err(
+            "Onboarded viewers need at least one key to read",
+        ));
+    }
+
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    if let Some(entropy) = &entropy {
+        check_viewer_entropy(deps.storage, &viewer_raw, entropy)?;
+    }
+    add_viewer(
+        deps.storage,
+        &mut state,
+        &viewer,
+        &viewer_raw,
+        env.block.height,
+    )?;
+    if let Some(allowed_viewer) = state
+        .allowed_viewers
+        .iter_mut()
+        .find(|v| v.address == viewer_raw)
+    {
+        allowed_viewer.keys = Some(keys);
+    }
+    fit_storage_budget(&mut state)?;
+    config(deps.storage).save(&state)?;
+
+    let mut response = Response::new().add_attribute("status", "added");
+    if let Some(entropy) = entropy {
+        let ttl = viewer_key_ttl_blocks(deps.storage, &state, &viewer_raw)?;
+        let key = create_viewing_key(
+            deps.storage,
+            &info,
+            &env,
+            &viewer,
+            &viewer_raw,
+            &entropy,
+            ttl,
+        )?;
+        response = response.set_data(to_binary(&ViewingKeyResponse { key })?);
+    }
+
+    Ok(response)
+}
+
+/// Adds `viewer` to `state`, or queues it for approval when a governance
+/// contract is set. Returns the resulting status, the caller saves `state`
+fn add_viewer(
+    storage: &mut dyn Storage,
+    state: &mut State,
+    viewer: &str,
+    viewer_raw: &CanonicalAddr,
+    height: u64,
+) -> StdResult<&'static str> {
+    if state.is_allowed_viewer(viewer_raw) || state.pending_viewers.contains(viewer_raw) {
+        return Err(StdError::generic_err(format!(
+            "{} is already a viewer",
+            viewer
+        )));
+    }
+
+    if state.governance.is_some() {
+        state.pending_viewers.push(viewer_raw.clone());
+        return Ok("pending_governance");
+    }
+
+    record_membership_change(storage, viewer_raw, MembershipAction::Added, height)?;
+    state.allowed_viewers.push(AllowedViewer {
+        address: viewer_raw.clone(),
+        keys: None,
+    });
+    fit_storage_budget(state)?;
+    Ok("added")
+}
+
+/// Checks the invariants the other messages maintain, e.g. after a migration,
+/// and returns a report as response data. Nothing is written to storage
+pub fn try_self_test(deps: DepsMut, info: MessageInfo) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can run the self test",
+        ));
+    }
+
+    let mut viewers: Vec<&CanonicalAddr> =
+        state.allowed_viewers.iter().map(|v| &v.address).collect();
+    viewers.sort();
+    let viewer_count = viewers.len();
+    viewers.dedup();
+
+    let checks = vec![
+        (
+            "schema_version_current",
+            state.schema_version == SCHEMA_VERSION,
+        ),
+        (
+            "owner_valid",
+            deps.api.addr_humanize(&state.owner).is_ok()
+                && state.pending_owner.as_ref() != Some(&state.owner),
+        ),
+        ("no_duplicate_viewers", viewers.len() == viewer_count),
+        (
+            "no_viewer_pending_approval",
+            !state
+                .pending_viewers
+                .iter()
+                .any(|v| state.is_allowed_viewer(v)),
+        ),
+        (
+            "secret_variables_match_checksum",
+            checksum(&state.secret_variables)? == state.secret_variables_checksum,
+        ),
+        (
+            "within_storage_budget",
+            check_storage_budget(&state).is_ok(),
+        ),
+        (
+            "read_quorum_valid",
+            !matches!(&state.read_quorum, Some(ReadQuorum { quorum: 0, .. })),
+        ),
+        (
+            "viewing_key_ttl_valid",
+            check_viewing_key_ttl(state.viewing_key_ttl_blocks).is_ok(),
+        ),
+        (
+            "history_within_cap",
+            state.secret_variables_history.len() <= state.max_history_len as usize,
+        ),
+        (
+            "read_once_keys_stored",
+            state
+                .read_once_keys
+                .iter()
+                .all(|k| state.secret_variables.contains_key(k)),
+        ),
+    ];
+
+    let checks: Vec<SelfTestCheck> = checks
+        .into_iter()
+        .map(|(name, ok)| SelfTestCheck {
+            name: name.to_string(),
+            ok,
+        })
+        .collect();
+    let report = SelfTestResponse {
+        ok: checks.iter().all(|c| c.ok),
+        checks,
+    };
+
+    Ok(Response::new().set_data(to_binary(&report)?))
+}
+
+/// Permanently rejects the execute messages named in `actions`, e.g. `set_viewers`
+/// once setup is done. There is no way to enable them again, and a name that is
+/// not an execute message is an error rather than silently disabling nothing
+pub fn try_disable_actions(
+    deps: DepsMut,
+    info: MessageInfo,
+    actions: Vec<String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err("Only the owner can disable actions"));
+        }
+
+        if let Some(unknown) = actions.iter().find(|a| !ACTIONS.contains(&a.as_str())) {
+            return Err(StdError::generic_err(format!(
+                "Unknown action: {}",
+                unknown
+            )));
+        }
+        for action in actions {
+            if !state.disabled_actions.contains(&action) {
+                state.disabled_actions.push(action);
+            }
+        }
+        Ok(state)
+    })?;
+
+    deps.api.debug("actions disabled successfully");
+    Ok(Response::default())
+}
+
+/// Sets the public key that signs viewer vouchers, `None` stops accepting vouchers
+pub fn try_set_voucher_pubkey(
+    deps: DepsMut,
+    info: MessageInfo,
+    pubkey: Option<Binary>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the voucher public key",
+            ));
+        }
+
+        if matches!(&pubkey, Some(pubkey) if pubkey.len() != COMPRESSED_PUBKEY_LENGTH) {
+            return Err(StdError::generic_err(
+                "Voucher public key must be a compressed secp256k1 key",
+            ));
+        }
+
+        state.voucher_pubkey = pubkey;
+        Ok(state)
+    })?;
+
+    deps.api.debug("voucher public key set successfully");
+    Ok(Response::default())
+}
+
+/// What the owner signs for a voucher, so it is only valid for one contract on one chain
+#[derive(Serialize)]
+struct VoucherPayload<'a> {
+    chain_id: &'a str,
+    contract: &'a str,
+    viewer: &'a str,
+    expires_at_height: u64,
+}
+
+/// Adds the sender as a viewer on the strength of a voucher the owner signed off-chain.
+///
+/// `signature` is a secp256k1 signature by `voucher_pubkey` over the sha256 of
+/// the JSON encoded `VoucherPayload`, `{"chain_id", "contract", "viewer",
+/// "expires_at_height"}` in that order. Each voucher can be redeemed once
+pub fn try_redeem_voucher(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    voucher: Voucher,
+    signature: Binary,
+) -> StdResult<Response> {
+    let mut state = config_read(deps.storage).load()?;
+    let pubkey = state
+        .voucher_pubkey
+        .clone()
+        .ok_or_else(|| StdError::generic_err("Vouchers are not accepted"))?;
+
+    if voucher.viewer != info.sender.as_str() {
+        return Err(StdError::generic_err(
+            "Vouchers can only be redeemed by the viewer they name",
+        ));
+    }
+
+    if voucher.expires_at_height <= env.block.height {
+        return Err(StdError::generic_err("Voucher has expired"));
+    }
+
+    let payload = VoucherPayload {
+        chain_id: &env.block.chain_id,
+        contract: env.contract.address.as_str(),
+        viewer: &voucher.viewer,
+        expires_at_height: voucher.expires_at_height,
+    };
+    let voucher_hash = sha_256(&to_vec(&payload)?);
+    let verified = deps
+        .api
+        .secp256k1_verify(&voucher_hash, signature.as_slice(), pubkey.as_slice())
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    if !verified {
+        return Err(StdError::generic_err("Voucher was not signed by the owner"));
+    }
+
+    if redeemed_vouchers_read(deps.storage)
+        .may_load(&voucher_hash)?
+        .is_some()
+    {
+        return Err(StdError::generic_err("Voucher was already redeemed"));
+    }
+
+    let viewer_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let status = add_viewer(
+        deps.storage,
+        &mut state,
+        &voucher.viewer,
+        &viewer_raw,
+        env.block.height,
+    )?;
+
+    config(deps.storage).save(&state)?;
+    redeemed_vouchers(deps.storage).save(&voucher_hash, &true)?;
+
+    Ok(Response::new().add_attribute("status", status))
+}
+
+/// Lets a viewer remove itself from the allowed viewers
+pub fn try_leave_as_viewer(deps: DepsMut, env: Env, info: MessageInfo) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    let position = state
+        .allowed_viewers
+        .iter()
+        .position(|v| v.address == sender_address_raw)
+        .ok_or_else(|| StdError::generic_err("Only allowed viewers can leave"))?;
+    state.allowed_viewers.remove(position);
+    prune_access_counts(&mut state);
+    config(deps.storage).save(&state)?;
+
+    record_membership_change(
+        deps.storage,
+        &sender_address_raw,
+        MembershipAction::Removed,
+        env.block.height,
+    )?;
+    invalidate_viewing_key(
+        deps.storage,
+        &info,
+        &env,
+        info.sender.as_str(),
+        &sender_address_raw,
+    )?;
+    forget_viewer_settings(deps.storage, &sender_address_raw);
+
+    deps.api.debug("viewer left successfully");
+    Ok(Response::default())
+}
+
+/// Requires `read_quorum.quorum` viewers to co-sign before the secret variables can be read.
+///
+/// Any co-signatures collected under the previous setting are dropped.
+pub fn try_set_read_quorum(
+    deps: DepsMut,
+    info: MessageInfo,
+    read_quorum: Option<ReadQuorum>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the read quorum",
+            ));
+        }
+
+        if matches!(read_quorum, Some(ReadQuorum { quorum: 0, .. })) {
+            return Err(StdError::generic_err("Read quorum must be at least 1"));
+        }
+
+        state.read_quorum = read_quorum;
+        state.read_cosigns = vec![];
+        Ok(state)
+    })?;
+
+    deps.api.debug("read quorum set successfully");
+    Ok(Response::default())
+}
+
+/// Same read as the `GetSecretVariables` query, but as a transaction so the
+/// read can be counted. The secret variables are returned as response data
+pub fn try_get_secret_variables_audited(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewing_key: String,
+    keys: Option<Vec<String>>,
+) -> StdResult<Response> {
+    let mut state = authenticate_viewer(deps.as_ref(), &env, info.sender.as_str(), &viewing_key)?;
+    check_no_read_quorum(&state)?;
+    check_reveal_condition(deps.as_ref(), &state)?;
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let secret_variables = scoped_secret_variables(&state, &env, &sender_address_raw, keys)?;
+
+    match state
+        .access_counts
+        .iter_mut()
+        .find(|c| c.viewer == sender_address_raw)
+    {
+        Some(access_count) => access_count.count += 1,
+        None => state.access_counts.push(AccessCount {
+            viewer: sender_address_raw,
+            count: 1,
+        }),
+    }
+    config(deps.storage).save(&state)?;
+
+    Ok(Response::new().set_data(to_binary(&secret_variables)?))
+}
+
+/// Drops the access counts and scheduled removals of former viewers, so a
+/// viewer that is added again starts at zero and without a removal date
+fn prune_access_counts(state: &mut State) {
+    let allowed_viewers = &state.allowed_viewers;
+    state
+        .access_counts
+        .retain(|c| allowed_viewers.iter().any(|v| v.address == c.viewer));
+    state
+        .scheduled_removals
+        .retain(|r| allowed_viewers.iter().any(|v| v.address == r.viewer));
+}
+
+/// Sets how many blocks viewing keys generated from now on stay valid, for
+/// every viewer that was added without a TTL of its own
+pub fn try_set_viewing_key_ttl(
+    deps: DepsMut,
+    info: MessageInfo,
+    viewing_key_ttl_blocks: Option<u64>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the viewing key TTL",
+            ));
+        }
+
+        check_viewing_key_ttl(viewing_key_ttl_blocks)?;
+        state.viewing_key_ttl_blocks = viewing_key_ttl_blocks;
+        Ok(state)
+    })?;
+
+    deps.api.debug("viewing key ttl set successfully");
+    Ok(Response::default())
+}
+
+fn check_viewing_key_ttl(viewing_key_ttl_blocks: Option<u64>) -> StdResult<()> {
+    if viewing_key_ttl_blocks == Some(0) {
+        return Err(StdError::generic_err(
+            "Viewing key TTL must be at least 1 block",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Records the caller's co-signature for a quorum read.
+///
+/// The co-signature that completes the quorum gets the secret variables as
+/// response data and resets the collected co-signatures, so every quorum
+/// unlocks exactly one read.
+pub fn try_cosign_read(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewing_key: String,
+) -> StdResult<Response> {
+    let mut state = authenticate_viewer(deps.as_ref(), &env, info.sender.as_str(), &viewing_key)?;
+    check_reveal_condition(deps.as_ref(), &state)?;
+    let read_quorum = state
+        .read_quorum
+        .clone()
+        .ok_or_else(|| StdError::generic_err("No read quorum is configured"))?;
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let height = env.block.height;
+    state.read_cosigns.retain(|c| {
+        c.height + read_quorum.window_blocks >= height && c.viewer != sender_address_raw
+    });
+    state.read_cosigns.push(ReadCosign {
+        viewer: sender_address_raw.clone(),
+        height,
+    });
+
+    if (state.read_cosigns.len() as u32) < read_quorum.quorum {
+        let cosigns = state.read_cosigns.len().to_string();
+        config(deps.storage).save(&state)?;
+        return Ok(Response::new().add_attribute("cosigns", cosigns));
+    }
+
+    state.read_cosigns = vec![];
+    config(deps.storage).save(&state)?;
+
+    let secret_variables = scoped_secret_variables(&state, &env, &sender_address_raw, None)?;
+    Ok(Response::new()
+        .add_attribute("cosigns", read_quorum.quorum.to_string())
+        .set_data(to_binary(&secret_variables)?))
+}
+
+/// Invalidates the viewing keys of `viewers`, e.g. after a breach.
+///
+/// The viewers stay allowed and can generate new keys. The `revoked`
+/// attribute counts the viewers that actually held a key.
+pub fn try_revoke_viewing_keys_for(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewers: Vec<String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can revoke viewing keys",
+        ));
+    }
+
+    let mut revoked = 0u32;
+    for viewer in &viewers {
+        let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+        if has_key_read(deps.storage)
+            .may_load(viewer_raw.as_slice())?
+            .is_some()
+        {
+            revoked += 1;
+        }
+        invalidate_viewing_key(deps.storage, &info, &env, viewer, &viewer_raw)?;
+    }
+
+    deps.api.debug("viewing keys revoked successfully");
+    Ok(Response::new().add_attribute("revoked", revoked.to_string()))
+}
+
+/// Removes a single viewer and invalidates its viewing key
+pub fn try_revoke_viewer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewer: String,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err("Only the owner can revoke viewers"));
+    }
+
+    let position = state
+        .allowed_viewers
+        .iter()
+        .position(|v| v.address == viewer_raw)
+        .ok_or_else(|| StdError::generic_err(format!("{} is not an allowed viewer", viewer)))?;
+    state.allowed_viewers.remove(position);
+    prune_access_counts(&mut state);
+    config(deps.storage).save(&state)?;
+
+    record_membership_change(
+        deps.storage,
+        &viewer_raw,
+        MembershipAction::Removed,
+        env.block.height,
+    )?;
+    invalidate_viewing_key(deps.storage, &info, &env, &viewer, &viewer_raw)?;
+    forget_viewer_settings(deps.storage, &viewer_raw);
+
+    deps.api.debug("viewer revoked successfully");
+    Ok(Response::default())
+}
+
+/// Keeps `viewer` configured but denies it access from `remove_at_height` on,
+/// replacing any removal scheduled for it before
+pub fn try_schedule_viewer_removal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewer: String,
+    remove_at_height: Option<u64>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can schedule viewer removals",
+            ));
+        }
+        if !state.is_allowed_viewer(&viewer_raw) {
+            return Err(StdError::generic_err(format!(
+                "{} is not an allowed viewer",
+                viewer
+            )));
+        }
+
+        state.scheduled_removals.retain(|r| r.viewer != viewer_raw);
+        if let Some(remove_at_height) = remove_at_height {
+            if remove_at_height <= env.block.height {
+                return Err(StdError::generic_err(
+                    "Removal height must be after the current block, use RevokeViewer to remove now",
+                ));
+            }
+            state.scheduled_removals.push(ScheduledRemoval {
+                viewer: viewer_raw,
+                remove_at_height,
+            });
+        }
+
+        Ok(state)
+    })?;
+
+    Ok(Response::default())
+}
+
+/// Governance callback that turns a pending viewer into an allowed viewer
+pub fn try_approve_viewer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewer: String,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if state.governance.as_ref() != Some(&sender_address_raw) {
+            return Err(StdError::generic_err(
+                "Only the governance contract can approve viewers",
+            ));
+        }
+
+        let position = state
+            .pending_viewers
+            .iter()
+            .position(|v| *v == viewer_raw)
+            .ok_or_else(|| {
+                StdError::generic_err(format!("{} is not waiting for approval", viewer))
+            })?;
+
+        state.pending_viewers.remove(position);
+        state.allowed_viewers.push(AllowedViewer {
+            address: viewer_raw.clone(),
+            keys: None,
+        });
+        fit_storage_budget(&mut state)?;
+
+        Ok(state)
+    })?;
+
+    record_membership_change(
+        deps.storage,
+        &viewer_raw,
+        MembershipAction::Added,
+        env.block.height,
+    )?;
+
+    deps.api.debug("viewer approved successfully");
+    Ok(Response::default())
+}
+
+pub fn try_set_nft_gate(
+    deps: DepsMut,
+    info: MessageInfo,
+    nft_gate: Option<NftGate>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err("Only the owner can set the NFT gate"));
+        }
+
+        state.nft_gate = nft_gate;
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("nft gate set successfully");
+    Ok(Response::default())
+}
+
+pub fn try_set_delegation_gate(
+    deps: DepsMut,
+    info: MessageInfo,
+    delegation_gate: Option<DelegationGate>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    // a zero minimum would let every permit signer read, delegating or not
+    if let Some(delegation_gate) = &delegation_gate {
+        if delegation_gate.min_delegation.is_zero() {
+            return Err(StdError::generic_err(
+                "The minimum delegation has to be greater than zero",
+            ));
+        }
+        if deps
+            .querier
+            .query_validator(delegation_gate.validator.as_str())?
+            .is_none()
+        {
+            return Err(StdError::generic_err(format!(
+                "Unknown validator: {}",
+                delegation_gate.validator
+            )));
+        }
+    }
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the delegation gate",
+            ));
+        }
+
+        state.delegation_gate = delegation_gate;
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("delegation gate set successfully");
+    Ok(Response::default())
+}
+
+/// Holds back the secret variables until `reveal_oracle` reports its condition as met,
+/// `None` reveals them without asking
+pub fn try_set_reveal_oracle(
+    deps: DepsMut,
+    info: MessageInfo,
+    reveal_oracle: Option<RevealOracle>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the reveal oracle",
+            ));
+        }
+
+        state.reveal_oracle = reveal_oracle;
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("reveal oracle set successfully");
+    Ok(Response::default())
+}
+
+/// Stores `metadata` for an allowed viewer, `None` clears it
+pub fn try_set_viewer_metadata(
+    deps: DepsMut,
+    info: MessageInfo,
+    viewer: String,
+    metadata: Option<String>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can set viewer metadata",
+        ));
+    }
+
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    if !state.is_allowed_viewer(&viewer_raw) {
+        return Err(StdError::generic_err(format!(
+            "{} is not an allowed viewer",
+            viewer
+        )));
+    }
+
+    match metadata {
+        Some(metadata) => viewer_metadata(deps.storage).save(viewer_raw.as_slice(), &metadata)?,
+        None => viewer_metadata(deps.storage).remove(viewer_raw.as_slice()),
+    }
+
+    deps.api.debug("viewer metadata set successfully");
+    Ok(Response::default())
+}
+
+/// Requires `viewer` to supply at least `min_entropy_length` bytes of entropy
+/// whenever it generates a viewing key
+pub fn try_set_viewer_min_entropy(
+    deps: DepsMut,
+    info: MessageInfo,
+    viewer: String,
+    min_entropy_length: Option<u32>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner {
+        return Err(StdError::generic_err(
+            "Only the owner can set viewer entropy requirements",
+        ));
+    }
+
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    if !state.is_allowed_viewer(&viewer_raw) {
+        return Err(StdError::generic_err(format!(
+            "{} is not an allowed viewer",
+            viewer
+        )));
+    }
+
+    match min_entropy_length {
+        Some(length) => viewer_min_entropy(deps.storage).save(viewer_raw.as_slice(), &length)?,
+        None => viewer_min_entropy(deps.storage).remove(viewer_raw.as_slice()),
+    }
+
+    Ok(Response::default())
+}
+
+/// Limits which secret keys a viewer can read, `None` grants every key.
+/// Scoping an address that is not a viewer yet adds it as one
+pub fn try_set_viewer_scope(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    viewer: String,
+    keys: Option<Vec<String>>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    let mut added = false;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set viewer scopes",
+            ));
+        }
+
+        match state
+            .allowed_viewers
+            .iter_mut()
+            .find(|v| v.address == viewer_raw)
+        {
+            Some(allowed_viewer) => allowed_viewer.keys = keys,
+            None if state.governance.is_some() => {
+                return Err(StdError::generic_err(
+                    "Viewers wait for governance approval, use AddViewer instead",
+                ))
+            }
+            None => {
+                state.allowed_viewers.push(AllowedViewer {
+                    address: viewer_raw.clone(),
+                    keys,
+                });
+                added = true;
+            }
+        }
+        fit_storage_budget(&mut state)?;
+
+        Ok(state)
+    })?;
+
+    if added {
+        record_membership_change(
+            deps.storage,
+            &viewer_raw,
+            MembershipAction::Added,
+            env.block.height,
+        )?;
+    }
+
+    deps.api.debug("viewer scope set successfully");
+    Ok(Response::default())
+}
+
+/// Commits to the manifest describing the expected secret variables, so clients
+/// can check they are talking to the configuration they expect
+pub fn try_set_manifest_hash(
+    deps: DepsMut,
+    info: MessageInfo,
+    manifest_hash: Option<Binary>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the manifest hash",
+            ));
+        }
+
+        state.manifest_hash = manifest_hash;
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("manifest hash set successfully");
+    Ok(Response::default())
+}
+
+/// Stores a hash of an off-chain recovery identifier, never the identifier itself
+pub fn try_set_recovery_hint_hash(
+    deps: DepsMut,
+    info: MessageInfo,
+    recovery_hint_hash: Option<Binary>,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err(
+                "Only the owner can set the recovery hint hash",
+            ));
+        }
+
+        state.recovery_hint_hash = recovery_hint_hash;
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("recovery hint hash set successfully");
+    Ok(Response::default())
+}
+
+/// Appends an entry to the membership log, which is never rewritten
+fn record_membership_change(
+    storage: &mut dyn Storage,
+    viewer: &CanonicalAddr,
+    action: MembershipAction,
+    height: u64,
+) -> StdResult<()> {
+    if action == MembershipAction::Added {
+        last_grant_height(storage).save(&height)?;
+    }
+
+    let index = membership_log_len_read(storage).may_load()?.unwrap_or(0);
+
+    membership_log(storage).save(
+        &index.to_be_bytes(),
+        &MembershipChange {
+            viewer: viewer.clone(),
+            action,
+            height,
+        },
+    )?;
+    membership_log_len(storage).save(&(index + 1))
+}
+
+/// Replaces the viewing key of `account` with a fresh random one that is never revealed
+fn invalidate_viewing_key(
+    storage: &mut dyn Storage,
+    info: &MessageInfo,
+    env: &Env,
+    account: &str,
+    account_raw: &CanonicalAddr,
+) -> StdResult<()> {
+    ViewingKey::create(storage, info, env, account, b"invalidated");
+    viewing_key_expiry(storage).remove(account_raw.as_slice());
+    if has_key_read(storage)
+        .may_load(account_raw.as_slice())?
+        .is_some()
+    {
+        has_key(storage).remove(account_raw.as_slice());
+        let live_keys = live_viewing_keys_read(storage).may_load()?.unwrap_or(0);
+        live_viewing_keys(storage).save(&live_keys.saturating_sub(1))?;
+    }
+    Ok(())
+}
+
+/// Drops the settings kept for `viewer_raw` outside of `State`, so a removed
+/// viewer that is added back starts from the defaults
+fn forget_viewer_settings(storage: &mut dyn Storage, viewer_raw: &CanonicalAddr) {
+    viewer_key_ttl(storage).remove(viewer_raw.as_slice());
+    viewer_min_entropy(storage).remove(viewer_raw.as_slice());
+    viewer_metadata(storage).remove(viewer_raw.as_slice());
+}
+
+/// Number of entries kept in the recent actions feed
+const RECENT_ACTIONS_CAP: usize = 20;
+
+fn record_recent_action(
+    storage: &mut dyn Storage,
+    action: &str,
+    actor: CanonicalAddr,
+    height: u64,
+) -> StdResult<()> {
+    let mut actions = recent_actions_read(storage).may_load()?.unwrap_or_default();
+    actions.push(RecentAction {
+        action: action.to_string(),
+        actor,
+        height,
+    });
+    if actions.len() > RECENT_ACTIONS_CAP {
+        actions.drain(..actions.len() - RECENT_ACTIONS_CAP);
+    }
+    recent_actions(storage).save(&actions)
+}
+
+/// Name of the message as it appears in the JSON
+fn action_name(msg: &ExecuteMsg) -> &'static str {
+    match msg {
+        ExecuteMsg::SetViewers { .. } => "set_viewers",
+        ExecuteMsg::SetSecretVariables { .. } => "set_secret_variables",
+        ExecuteMsg::InitSecretVariables { .. } => "init_secret_variables",
+        ExecuteMsg::SetSecretVariable { .. } => "set_secret_variable",
+        ExecuteMsg::RemoveSecretVariable { .. } => "remove_secret_variable",
+        ExecuteMsg::SetReadOnce { .. } => "set_read_once",
+        ExecuteMsg::SetReadableUntil { .. } => "set_readable_until",
+        ExecuteMsg::AcknowledgeRead { .. } => "acknowledge_read",
+        ExecuteMsg::RollbackSecretVariables { .. } => "rollback_secret_variables",
+        ExecuteMsg::SetVoucherPubkey { .. } => "set_voucher_pubkey",
+        ExecuteMsg::DisableActions { .. } => "disable_actions",
+        ExecuteMsg::SelfTest {} => "self_test",
+        ExecuteMsg::RedeemVoucher { .. } => "redeem_voucher",
+        ExecuteMsg::GenerateViewingKey { .. } => "generate_viewing_key",
+        ExecuteMsg::SetPublicVariables { .. } => "set_public_variables",
+        ExecuteMsg::ReportFailedAuth { .. } => "report_failed_auth",
+        ExecuteMsg::ImportViewersCsv { .. } => "import_viewers_csv",
+        ExecuteMsg::SetNftGate { .. } => "set_nft_gate",
+        ExecuteMsg::SetDelegationGate { .. } => "set_delegation_gate",
+        ExecuteMsg::SetRevealOracle { .. } => "set_reveal_oracle",
+        ExecuteMsg::SetRecoveryHintHash { .. } => "set_recovery_hint_hash",
+        ExecuteMsg::SetSecretReference { .. } => "set_secret_reference",
+        ExecuteMsg::Pause { .. } => "pause",
+        ExecuteMsg::Unpause {} => "unpause",
+        ExecuteMsg::SetGovernance { .. } => "set_governance",
+        ExecuteMsg::AddViewer { .. } => "add_viewer",
+        ExecuteMsg::OnboardViewer { .. } => "onboard_viewer",
+        ExecuteMsg::LeaveAsViewer {} => "leave_as_viewer",
+        ExecuteMsg::SetReadQuorum { .. } => "set_read_quorum",
+        ExecuteMsg::SetViewingKeyTtl { .. } => "set_viewing_key_ttl",
+        ExecuteMsg::CosignRead { .. } => "cosign_read",
+        ExecuteMsg::GetSecretVariablesAudited { .. } => "get_secret_variables_audited",
+        ExecuteMsg::SetViewerMetadata { .. } => "set_viewer_metadata",
+        ExecuteMsg::SetViewerMinEntropy { .. } => "set_viewer_min_entropy",
+        ExecuteMsg::SetViewerScope { .. } => "set_viewer_scope",
+        ExecuteMsg::StageSecrets { .. } => "stage_secrets",
+        ExecuteMsg::CommitStaged {} => "commit_staged",
+        ExecuteMsg::DiscardStaged {} => "discard_staged",
+        ExecuteMsg::SetManifestHash { .. } => "set_manifest_hash",
+        ExecuteMsg::RevokeViewingKeysFor { .. } => "revoke_viewing_keys_for",
+        ExecuteMsg::RevokeViewer { .. } => "revoke_viewer",
+        ExecuteMsg::ScheduleViewerRemoval { .. } => "schedule_viewer_removal",
+        ExecuteMsg::TransferOwnership { .. } => "transfer_ownership",
+        ExecuteMsg::AcceptOwnership {} => "accept_ownership",
+        ExecuteMsg::ApproveViewer { .. } => "approve_viewer",
+    }
+}
+
+/// Every name `action_name` returns, for checking what may be disabled
+const ACTIONS: &[&str] = &[
+    "set_viewers",
+    "set_secret_variables",
+    "init_secret_variables",
+    "set_secret_variable",
+    "remove_secret_variable",
+    "set_read_once",
+    "set_readable_until",
+    "acknowledge_read",
+    "rollback_secret_variables",
+    "set_voucher_pubkey",
+    "disable_actions",
+    "self_test",
+    "redeem_voucher",
+    "generate_viewing_key",
+    "set_public_variables",
+    "report_failed_auth",
+    "import_viewers_csv",
+    "set_nft_gate",
+    "set_delegation_gate",
+    "set_reveal_oracle",
+    "set_recovery_hint_hash",
+    "set_secret_reference",
+    "pause",
+    "unpause",
+    "set_governance",
+    "add_viewer",
+    "onboard_viewer",
+    "leave_as_viewer",
+    "set_read_quorum",
+    "set_viewing_key_ttl",
+    "cosign_read",
+    "get_secret_variables_audited",
+    "set_viewer_metadata",
+    "set_viewer_min_entropy",
+    "set_viewer_scope",
+    "stage_secrets",
+    "commit_staged",
+    "discard_staged",
+    "set_manifest_hash",
+    "revoke_viewing_keys_for",
+    "revoke_viewer",
+    "schedule_viewer_removal",
+    "transfer_ownership",
+    "accept_ownership",
+    "approve_viewer",
+];
+
+fn checksum(secret_variables: &BTreeMap<String, String>) -> StdResult<Binary> {
+    Ok(Binary::from(sha_256(&to_vec(secret_variables)?).to_vec()))
+}
+
+fn secret_bytes(secret_variables: &BTreeMap<String, String>) -> usize {
+    secret_variables
+        .iter()
+        .map(|(k, v)| k.len() + v.len())
+        .sum()
+}
+
+/// Approximate number of bytes taken by the secret variables, including their
+/// staged and earlier versions, and the viewer list
+fn storage_bytes(state: &State) -> u64 {
+    let viewer_bytes: usize = state
+        .allowed_viewers
+        .iter()
+        .map(|v| {
+            let scope_bytes: usize = v.keys.iter().flatten().map(String::len).sum();
+            v.address.as_slice().len() + scope_bytes
+        })
+        .sum();
+    let staged_bytes = state
+        .staged_secret_variables
+        .as_ref()
+        .map_or(0, secret_bytes);
+    let fallback_bytes = secret_bytes(&state.expired_fallbacks);
+    let history_bytes: usize = state
+        .secret_variables_history
+        .iter()
+        .map(|(_, secret_variables)| secret_bytes(secret_variables))
+        .sum();
+    (secret_bytes(&state.secret_variables)
+        + staged_bytes
+        + fallback_bytes
+        + history_bytes
+        + viewer_bytes) as u64
+}
+
+/// Drops the oldest earlier versions of the secret variables until the state
+/// fits `max_total_bytes`, so a full history never blocks a write, then checks it
+fn fit_storage_budget(state: &mut State) -> StdResult<()> {
+    if let Some(max_total_bytes) = state.max_total_bytes {
+        while storage_bytes(state) > max_total_bytes && !state.secret_variables_history.is_empty() {
+            state.secret_variables_history.remove(0);
+        }
+    }
+    check_storage_budget(state)
+}
+
+fn check_storage_budget(state: &State) -> StdResult<()> {
+    match state.max_total_bytes {
+        Some(max_total_bytes) if storage_bytes(state) > max_total_bytes => {
+            Err(StdError::generic_err(format!(
+                "Storage budget of {} bytes exceeded",
+                max_total_bytes
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Records a failed viewing key authentication for `account`.
+///
+/// Queries cannot write to storage, so failures are reported through this
+/// message by the owner or by the account itself. Once `max_failed_auths`
+/// failures are reported the account cannot query for `lockout_blocks` blocks,
+/// and once `auto_pause_threshold` failures reported by the owner fall within
+/// `auto_pause_window_blocks` all secret reads are paused until the owner
+/// unpauses the contract. Accounts reporting themselves only count towards
+/// their own lockout, or anyone could pause every viewer's reads.
+pub fn try_report_failed_auth(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    account: String,
+    viewing_key: String,
+) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let account_raw = deps.api.addr_canonicalize(account.as_str())?;
+    let state = config_read(deps.storage).load()?;
+
+    if sender_address_raw != state.owner && sender_address_raw != account_raw {
+        return Err(StdError::generic_err(
+            "Only the owner or the account itself can report failed authentication",
+        ));
+    }
+
+    if state.max_failed_auths == 0 && state.auto_pause_threshold == 0 {
+        return Err(StdError::generic_err(
+            "Failed authentication tracking is disabled",
+        ));
+    }
+
+    if ViewingKey::check(deps.storage, account.as_ref(), viewing_key.as_ref()).is_ok() {
+        return Err(StdError::generic_err(
+            "Viewing key is valid, there is no failure to report",
+        ));
+    }
+
+    let mut record = failed_auths_read(deps.storage)
+        .may_load(account_raw.as_slice())?
+        .unwrap_or_default();
+
+    // a lockout that has run out starts the count over
+    if matches!(record.locked_until, Some(height) if height <= env.block.height) {
+        record = Default::default();
+    }
+
+    record.count += 1;
+    if state.max_failed_auths > 0
+        && record.count >= state.max_failed_auths
+        && record.locked_until.is_none()
+    {
+        record.locked_until = Some(env.block.height + state.lockout_blocks);
+    }
+
+    failed_auths(deps.storage).save(account_raw.as_slice(), &record)?;
+
+    let mut response = Response::new().add_attribute("failed_auths", record.count.to_string());
+
+    if state.auto_pause_threshold > 0 && sender_address_raw == state.owner {
+        let paused = config(deps.storage).update(|mut state| -> Result<_, StdError> {
+            let window_start = env
+                .block
+                .height
+                .saturating_sub(state.auto_pause_window_blocks);
+            state
+                .recent_failed_auths
+                .retain(|height| *height > window_start);
+            state.recent_failed_auths.push(env.block.height);
+
+            if state.recent_failed_auths.len() as u32 >= state.auto_pause_threshold && !state.paused
+            {
+                state.paused = true;
+                state.pause_reason = Some("Too many failed authentications".to_string());
+            }
+
+            Ok(state)
+        })?;
+
+        if paused.paused {
+            response = response.add_attribute("paused", "true");
+        }
+    }
+
+    Ok(response)
+}
+
+/// Pauses all secret reads, `reason` is published as a plaintext attribute
+pub fn try_pause(deps: DepsMut, info: MessageInfo, reason: Option<String>) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err("Only the owner can pause"));
+        }
+
+        state.paused = true;
+        state.pause_reason = reason.clone();
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("contract paused successfully");
+    let mut response = Response::new().add_attribute_plaintext("paused", "true");
+    if let Some(reason) = reason {
+        response = response.add_attribute_plaintext("reason", reason);
+    }
+    Ok(response)
+}
+
+/// Resumes secret reads after the contract paused itself
+pub fn try_unpause(deps: DepsMut, info: MessageInfo) -> StdResult<Response> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    config(deps.storage).update(|mut state| -> Result<_, StdError> {
+        if sender_address_raw != state.owner {
+            return Err(StdError::generic_err("Only the owner can unpause"));
+        }
+
+        state.paused = false;
+        state.pause_reason = None;
+        state.recent_failed_auths.clear();
+
+        Ok(state)
+    })?;
+
+    deps.api.debug("contract unpaused successfully");
+    Ok(Response::default())
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetSecretVariables {
+            viewing_key,
+            account,
+            keys,
+        } => to_binary(&query_secret_variables(
+            deps,
+            env,
+            viewing_key,
+            account,
+            keys,
+        )?),
+        QueryMsg::CanonicalizeAddress { address } => {
+            to_binary(&query_canonicalize_address(deps, address)?)
+        }
+        QueryMsg::GetPublicVariables {} => to_binary(&query_public_variables(deps)?),
+        QueryMsg::GetSchemaVersion {} => to_binary(&query_schema_version(deps)?),
+        QueryMsg::HealthCheck {} => to_binary(&query_health_check(deps)?),
+        QueryMsg::GetStats {} => to_binary(&query_stats(deps)?),
+        QueryMsg::GetBlockInfo {} => to_binary(&query_block_info(env)),
+        QueryMsg::GetAuthModes {} => to_binary(&query_auth_modes(deps)?),
+        QueryMsg::GetOwnerActivity {} => to_binary(&query_owner_activity(deps, env)?),
+        QueryMsg::GetSecretVariablesWithNft {
+            account,
+            nft_viewing_key,
+        } => to_binary(&query_secret_variables_with_nft(
+            deps,
+            env,
+            account,
+            nft_viewing_key,
+        )?),
+        QueryMsg::GetSecretReference {
+            viewing_key,
+            account,
+        } => to_binary(&query_secret_reference(deps, env, viewing_key, account)?),
+        QueryMsg::EstimateSetGas { key, value } => {
+            to_binary(&query_estimate_set_gas(deps, key, value)?)
+        }
+        QueryMsg::GetMembershipHistory {
+            account,
+            viewing_key,
+            start,
+            limit,
+        } => to_binary(&query_membership_history(
+            deps,
+            account,
+            viewing_key,
+            start,
+            limit,
+        )?),
+        QueryMsg::OwnerDashboard {
+            account,
+            viewing_key,
+        } => to_binary(&query_owner_dashboard(deps, account, viewing_key)?),
+        QueryMsg::ListSecrets {
+            account,
+            viewing_key,
+            start_after,
+            limit,
+        } => to_binary(&query_list_secrets(
+            deps,
+            account,
+            viewing_key,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::FindViewingKeyOwner {
+            account,
+            viewing_key,
+            candidate_key,
+        } => to_binary(&query_find_viewing_key_owner(
+            deps,
+            account,
+            viewing_key,
+            candidate_key,
+        )?),
+        QueryMsg::GetRecoveryHintHash {
+            account,
+            viewing_key,
+        } => to_binary(&query_recovery_hint_hash(deps, account, viewing_key)?),
+        QueryMsg::IsAccessValid {
+            account,
+            viewing_key,
+        } => to_binary(&query_is_access_valid(deps, env, account, viewing_key)?),
+        QueryMsg::ExplainAuthorization {
+            account,
+            viewing_key,
+            key,
+        } => to_binary(&query_explain_authorization(
+            deps,
+            env,
+            account,
+            viewing_key,
+            key,
+        )?),
+        QueryMsg::CompareViewingKeys {
+            account,
+            viewing_key,
+            first,
+            second,
+        } => to_binary(&query_compare_viewing_keys(
+            deps,
+            env,
+            account,
+            viewing_key,
+            first,
+            second,
+        )?),
+        QueryMsg::ValidateAccessPlan {
+            account,
+            viewing_key,
+            plan,
+        } => to_binary(&query_validate_access_plan(
+            deps,
+            account,
+            viewing_key,
+            plan,
+        )?),
+        QueryMsg::BulkCheckAccess {
+            account,
+            viewing_key,
+            accounts,
+        } => to_binary(&query_bulk_check_access(
+            deps,
+            env,
+            account,
+            viewing_key,
+            accounts,
+        )?),
+        QueryMsg::VerifySecretMatches {
+            account,
+            viewing_key,
+            key,
+            candidate_hash,
+        } => to_binary(&query_verify_secret_matches(
+            deps,
+            env,
+            account,
+            viewing_key,
+            key,
+            candidate_hash,
+        )?),
+        QueryMsg::GetSecretVariablesHistory {
+            account,
+            viewing_key,
+        } => to_binary(&query_secret_variables_history(deps, account, viewing_key)?),
+        QueryMsg::WithPermit { permit, query } => permit_queries(deps, env, permit, query),
+        QueryMsg::GetRecentActions {
+            account,
+            viewing_key,
+        } => to_binary(&query_recent_actions(deps, account, viewing_key)?),
+        QueryMsg::GetAccessCounts {
+            account,
+            viewing_key,
+        } => to_binary(&query_access_counts(deps, account, viewing_key)?),
+        QueryMsg::GetPendingRemovals {
+            account,
+            viewing_key,
+        } => to_binary(&query_pending_removals(deps, account, viewing_key)?),
+        QueryMsg::GetPolicyDocument {
+            account,
+            viewing_key,
+        } => to_binary(&query_policy_document(deps, account, viewing_key)?),
+        QueryMsg::GetStateFingerprint {
+            account,
+            viewing_key,
+        } => to_binary(&query_state_fingerprint(deps, account, viewing_key)?),
+        QueryMsg::GetViewers {
+            account,
+            viewing_key,
+        } => to_binary(&query_viewers(deps, account, viewing_key)?),
+        QueryMsg::ListViewersWithoutKeys {
+            account,
+            viewing_key,
+        } => to_binary(&query_viewers_without_keys(deps, account, viewing_key)?),
+        QueryMsg::GetManifestHash {
+            account,
+            viewing_key,
+        } => to_binary(&query_manifest_hash(deps, env, account, viewing_key)?),
+        QueryMsg::GetViewerMetadata {
+            account,
+            viewing_key,
+            viewer,
+        } => to_binary(&query_viewer_metadata(deps, account, viewing_key, viewer)?),
+    }
+}
+
+fn query_secret_variables(
+    deps: Deps,
+    env: Env,
+    viewing_key: String,
+    account: String,
+    keys: Option<Vec<String>>,
+) -> StdResult<BTreeMap<String, String>> {
+    let state = authenticate_viewer(deps, &env, &account, &viewing_key)?;
+    check_no_read_quorum(&state)?;
+    check_reveal_condition(deps, &state)?;
+    let account_raw = deps.api.addr_canonicalize(&account)?;
+
+    scoped_secret_variables(&state, &env, &account_raw, keys)
+}
+
+/// Runs the same checks as the secret variable query, but reports the first
+/// one that fails instead of returning an error. Only the holder of the
+/// viewing key gets an answer, and the key is not checked while the account
+/// is locked out, so the query can neither guess keys nor reveal a lockout
+fn query_is_access_valid(
+    deps: Deps,
+    env: Env,
+    account: String,
+    viewing_key: String,
+) -> StdResult<AccessValidityResponse> {
+    let state = authenticate_account(deps, &env, &account, &viewing_key)?;
+    let account_raw = deps.api.addr_canonicalize(&account)?;
+
+    let reason = if state.paused {
+        Some(AccessDeniedReason::Paused)
+    } else if check_viewing_key_not_expired(deps, &env, &account_raw).is_err() {
+        Some(AccessDeniedReason::ExpiredViewingKey)
+    } else if !state.is_active_viewer(&account_raw, env.block.height) {
+        Some(AccessDeniedReason::NotAViewer)
+    } else {
+        unreadable_reason(deps, &state, &env, &account_raw)
+    };
+
+    Ok(AccessValidityResponse {
+        valid: reason.is_none(),
+        reason,
+    })
+}
+
+/// Why reading the secret variables fails for an authenticated viewer, going
+/// through the same checks as the secret variable query
+fn unreadable_reason(
+    deps: Deps,
+    state: &State,
+    env: &Env,
+    viewer: &CanonicalAddr,
+) -> Option<AccessDeniedReason> {
+    check_no_read_quorum(state)
+        .and_then(|_| check_reveal_condition(deps, state))
+        .and_then(|_| scoped_secret_variables(state, env, viewer, None))
+        .err()
+        .map(|e| AccessDeniedReason::Unreadable {
+            detail: error_detail(e),
+        })
+}
+
+fn error_detail(e: StdError) -> String {
+    match e {
+        StdError::GenericErr { msg, .. } => msg,
+        e => e.to_string(),
+    }
+}
+
+/// Runs every check reading `key` goes through, instead of stopping at the
+/// first failure. The checks are only run for the holder of the viewing key,
+/// which is authenticated the same way as for `IsAccessValid`
+fn query_explain_authorization(
+    deps: Deps,
+    env: Env,
+    account: String,
+    viewing_key: String,
+    key: String,
+) -> StdResult<ExplainAuthorizationResponse> {
+    let state = authenticate_account(deps, &env, &account, &viewing_key)?;
+    let account_raw = deps.api.addr_canonicalize(&account)?;
+
+    let check = |check: &str, result: StdResult<()>| AuthorizationCheck {
+        check: check.to_string(),
+        passed: result.is_ok(),
+        detail: result.err().map(error_detail),
+    };
+    let fail = |msg: String| Err(StdError::generic_err(msg));
+
+    let scope = state
+        .allowed_viewers
+        .iter()
+        .find(|v| v.address == account_raw)
+        .and_then(|v| v.keys.as_ref());
+
+    // the read check runs the read itself, which covers read once keys, the
+    // readable until height, the checksum and an off-chain secret reference
+    let checks = vec![
+        check("paused", check_not_paused(&state)),
+        check(
+            "viewing_key_expiry",
+            check_viewing_key_not_expired(deps, &env, &account_raw),
+        ),
+        check(
+            "membership",
+            if state.is_active_viewer(&account_raw, env.block.height) {
+                Ok(())
+            } else {
+                fail(format!("{} is not an allowed viewer", account))
+            },
+        ),
+        check(
+            "scope",
+            match scope {
+                Some(keys) if !keys.contains(&key) => {
+                    fail(format!("{} is outside the viewer's scope", key))
+                }
+                _ => Ok(()),
+            },
+        ),
+        check(
+            "exists",
+            if state.secret_variables.contains_key(&key) {
+                Ok(())
+            } else {
+                fail(format!("No secret variable named {}", key))
+            },
+        ),
+        check("read_quorum", check_no_read_quorum(&state)),
+        check("reveal_condition", check_reveal_condition(deps, &state)),
+        check(
+            "read",
+            scoped_secret_variables(&state, &env, &account_raw, Some(vec![key.clone()]))
+                .map(|_| ()),
+        ),
+    ];
+
+    Ok(ExplainAuthorizationResponse {
+        authorized: checks.iter().all(|c| c.passed),
+        checks,
+    })
+}
+
+/// Runs the checks of `IsAccessValid` for each of `accounts` on behalf of the
+/// owner, who does not know their viewing keys. An account that never
+/// generated a key, or had it revoked, is reported with an invalid viewing key
+fn query_bulk_check_access(
+    deps: Deps,
+    env: Env,
+    account: String,
+    viewing_key: String,
+    accounts: Vec<String>,
+) -> StdResult<BulkCheckAccessResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+    let has_key = has_key_read(deps.storage);
+
+    let accounts = accounts
+        .into_iter()
+        .map(|account| {
+            let account_raw = deps.api.addr_canonicalize(&account)?;
+            let reason = if state.paused {
+                Some(AccessDeniedReason::Paused)
+            } else if check_not_locked_out(deps, &env, &account_raw).is_err() {
+                Some(AccessDeniedReason::LockedOut)
+            } else if has_key.may_load(account_raw.as_slice())?.is_none() {
+                Some(AccessDeniedReason::InvalidViewingKey)
+            } else if check_viewing_key_not_expired(deps, &env, &account_raw).is_err() {
+                Some(AccessDeniedReason::ExpiredViewingKey)
+            } else if !state.is_active_viewer(&account_raw, env.block.height) {
+                Some(AccessDeniedReason::NotAViewer)
+            } else {
+                unreadable_reason(deps, &state, &env, &account_raw)
+            };
+
+            Ok((
+                account,
+                AccessValidityResponse {
+                    valid: reason.is_none(),
+                    reason,
+                },
+            ))
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(BulkCheckAccessResponse { accounts })
+}
+
+/// Lets the owner find accounts that hold more than one working key, without
+/// reporting failed authentications against either of them
+fn query_compare_viewing_keys(
+    deps: Deps,
+    env: Env,
+    account: String,
+    viewing_key: String,
+    first: AccountKey,
+    second: AccountKey,
+) -> StdResult<CompareViewingKeysResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let authenticates = |pair: &AccountKey, account_raw: &CanonicalAddr| {
+        ViewingKey::check(deps.storage, &pair.account, &pair.viewing_key).is_ok()
+            && check_viewing_key_not_expired(deps, &env, account_raw).is_ok()
+            && state.is_active_viewer(account_raw, env.block.height)
+    };
+
+    let first_raw = deps.api.addr_canonicalize(&first.account)?;
+    let second_raw = deps.api.addr_canonicalize(&second.account)?;
+
+    Ok(CompareViewingKeysResponse {
+        first_valid: authenticates(&first, &first_raw),
+        second_valid: authenticates(&second, &second_raw),
+        same_account: first_raw == second_raw,
+    })
+}
+
+/// Checks `plan` the way setting its viewers and scopes would, but collects
+/// every problem instead of stopping at the first one
+fn query_validate_access_plan(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+    plan: Vec<PlannedViewer>,
+) -> StdResult<ValidateAccessPlanResponse> {
+    let mut state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let mut errors = vec![];
+    let mut allowed_viewers: Vec<AllowedViewer> = vec![];
+    for planned in plan {
+        let address = match deps.api.addr_canonicalize(planned.viewer.as_str()) {
+            Ok(address) => address,
+            Err(_) => {
+                errors.push(format!("Invalid viewer address: {:?}", planned.viewer));
+                continue;
+            }
+        };
+        if allowed_viewers.iter().any(|v| v.address == address) {
+            errors.push(format!("Duplicate viewer: {}", planned.viewer));
+            continue;
+        }
+        allowed_viewers.push(AllowedViewer {
+            address,
+            keys: planned.keys,
+        });
+    }
+
+    state.allowed_viewers = allowed_viewers;
+    if let Err(StdError::GenericErr { msg, .. }) = fit_storage_budget(&mut state) {
+        errors.push(msg);
+    }
+
+    Ok(ValidateAccessPlanResponse {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+/// Same checks as the secret variable query, after which only the result of
+/// comparing hashes leaves the contract
+fn query_verify_secret_matches(
+    deps: Deps,
+    env: Env,
+    account: String,
+    viewing_key: String,
+    key: String,
+    candidate_hash: Binary,
+) -> StdResult<SecretMatchesResponse> {
+    let value = query_secret_variables(deps, env, viewing_key, account, Some(vec![key.clone()]))?
+        .remove(&key)
+        .ok_or_else(|| StdError::generic_err(format!("No secret variable named {}", key)))?;
+
+    Ok(SecretMatchesResponse {
+        matches: sha_256(value.as_bytes()).as_slice() == candidate_hash.as_slice(),
+    })
+}
+
+/// Runs `query` for the signer of `permit`, secret variables are only returned to allowed viewers
+/// and, when a delegation gate is set, to large enough delegators
+fn permit_queries(
+    deps: Deps,
+    env: Env,
+    permit: Permit,
+    query: QueryWithPermit,
+) -> StdResult<Binary> {
+    let account = validate(
+        deps,
+        PREFIX_REVOKED_PERMITS,
+        &permit,
+        env.contract.address.to_string(),
+        Some("secret"),
+    )?;
+    check_permit_expiry(&permit, &env)?;
+
+    if !permit.check_permission(&TokenPermissions::Owner) {
+        return Err(StdError::generic_err(format!(
+            "Permit must grant the owner permission, got {:?}",
+            permit.params.permissions
+        )));
+    }
+
+    let state = config_read(deps.storage).load()?;
+    let account_raw = deps.api.addr_canonicalize(&account)?;
+
+    match query {
+        QueryWithPermit::GetSecretVariables { keys } => {
+            check_not_paused(&state)?;
+            check_not_locked_out(deps, &env, &account_raw)?;
+
+            if !state.is_active_viewer(&account_raw, env.block.height) {
+                return Err(StdError::generic_err(
+                    "Only allowed viewers can query secret variables",
+                ));
+            }
+
+            check_no_read_quorum(&state)?;
+            check_reveal_condition(deps, &state)?;
+            to_binary(&scoped_secret_variables(&state, &env, &account_raw, keys)?)
+        }
+        // the permit proves the signer is the delegator, the staking module the amount
+        QueryWithPermit::GetSecretVariablesWithDelegation { keys } => {
+            check_not_paused(&state)?;
+            check_not_locked_out(deps, &env, &account_raw)?;
+            check_no_read_quorum(&state)?;
+            check_reveal_condition(deps, &state)?;
+            check_delegation(deps, &state, &account)?;
+            to_binary(&secret_variables_within(&state, &env, None, keys)?)
+        }
+        // answered for every signer, it reveals nothing but the signer's own role
+        QueryWithPermit::GetAllowedQueries {} => {
+            to_binary(&allowed_queries(deps, &state, &env, &account)?)
+        }
+    }
+}
+
+/// Errors once the height in a `<name>@<height>` permit name is reached. The
+/// signature covers the name, so the height cannot be changed after signing
+fn check_permit_expiry(permit: &Permit, env: &Env) -> StdResult<()> {
+    if let Some((_, height)) = permit.params.permit_name.rsplit_once('@') {
+        let expires_at_height: u64 = height.parse().map_err(|_| {
+            StdError::generic_err(format!("Invalid permit expiry height: {}", height))
+        })?;
+        if expires_at_height <= env.block.height {
+            return Err(StdError::generic_err(format!(
+                "Permit expired at height {}",
+                expires_at_height
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Errors unless `account` delegates at least the gate's minimum to its validator
+fn check_delegation(deps: Deps, state: &State, account: &str) -> StdResult<()> {
+    let delegation_gate = state
+        .delegation_gate
+        .as_ref()
+        .ok_or_else(|| StdError::generic_err("Delegation gated access is not configured"))?;
+
+    let delegated = deps
+        .querier
+        .query_delegation(account, delegation_gate.validator.as_str())?
+        .map_or(Uint128::zero(), |d| d.amount.amount);
+    if delegated < delegation_gate.min_delegation {
+        return Err(StdError::generic_err(format!(
+            "A delegation of at least {} to {} is required to query secret variables",
+            delegation_gate.min_delegation, delegation_gate.validator
+        )));
+    }
+
+    Ok(())
+}
+
+/// Queries that need no authentication, or authenticate against another contract
+const PUBLIC_QUERIES: &[&str] = &[
+    "canonicalize_address",
+    "get_public_variables",
+    "get_schema_version",
+    "health_check",
+    "get_stats",
+    "get_block_info",
+    "get_auth_modes",
+    "get_owner_activity",
+    "get_secret_variables_with_nft",
+    "estimate_set_gas",
+];
+
+/// Queries that authenticate an allowed viewer
+const VIEWER_QUERIES: &[&str] = &[
+    "get_secret_variables",
+    "get_secret_reference",
+    "get_manifest_hash",
+    "is_access_valid",
+    "explain_authorization",
+    "verify_secret_matches",
+];
+
+/// Queries that authenticate the owner
+const OWNER_QUERIES: &[&str] = &[
+    "get_membership_history",
+    "owner_dashboard",
+    "list_secrets",
+    "find_viewing_key_owner",
+    "get_recent_actions",
+    "get_state_fingerprint",
+    "get_viewers",
+    "list_viewers_without_keys",
+    "get_viewer_metadata",
+    "get_access_counts",
+    "bulk_check_access",
+    "compare_viewing_keys",
+    "validate_access_plan",
+    "get_pending_removals",
+    "get_policy_document",
+    "get_secret_variables_history",
+    "get_recovery_hint_hash",
+];
+
+/// The queries `account` is authorized for by its role, or by its delegation
+/// when a delegation gate is set. Whether a query currently succeeds, e.g.
+/// while reads are paused, is not taken into account
+fn allowed_queries(
+    deps: Deps,
+    state: &State,
+    env: &Env,
+    account: &str,
+) -> StdResult<AllowedQueriesResponse> {
+    let account_raw = deps.api.addr_canonicalize(account)?;
+    let mut queries = PUBLIC_QUERIES.to_vec();
+    if state.is_active_viewer(&account_raw, env.block.height) {
+        queries.extend_from_slice(VIEWER_QUERIES);
+    }
+    if account_raw == state.owner {
+        queries.extend_from_slice(OWNER_QUERIES);
+    }
+    if state.delegation_gate.is_some() && check_delegation(deps, state, account).is_ok() {
+        queries.push("get_secret_variables_with_delegation");
+    }
+
+    Ok(AllowedQueriesResponse {
+        queries: queries.into_iter().map(String::from).collect(),
+    })
+}
+
+/// Keeps only `keys`, if given. Keys that do not exist are left out, not reported.
+fn filter_secret_variables(
+    mut secret_variables: BTreeMap<String, String>,
+    keys: Option<Vec<String>>,
+) -> BTreeMap<String, String> {
+    if let Some(keys) = keys {
+        secret_variables.retain(|key, _| keys.contains(key));
+    }
+
+    secret_variables
+}
+
+/// Readable secret variables limited to the keys the viewer is scoped to,
+/// with any `ENV_TOKENS` in their values expanded
+fn scoped_secret_variables(
+    state: &State,
+    env: &Env,
+    viewer: &CanonicalAddr,
+    keys: Option<Vec<String>>,
+) -> StdResult<BTreeMap<String, String>> {
+    let scope = state
+        .allowed_viewers
+        .iter()
+        .find(|v| v.address == *viewer)
+        .and_then(|v| v.keys.clone());
+
+    secret_variables_within(state, env, scope, keys)
+}
+
+/// Readable secret variables limited to `scope` and then to `keys`, with any
+/// `ENV_TOKENS` in their values expanded. Every read path goes through here
+fn secret_variables_within(
+    state: &State,
+    env: &Env,
+    scope: Option<Vec<String>>,
+    keys: Option<Vec<String>>,
+) -> StdResult<BTreeMap<String, String>> {
+    if let Some(key) = keys
+        .iter()
+        .flatten()
+        .find(|k| state.consumed_keys.contains(k))
+    {
+        return Err(StdError::generic_err(format!(
+            "{} was a read once secret and has already been read",
+            key
+        )));
+    }
+
+    if let Some(key) = keys.iter().flatten().find(|k| {
+        is_past_readable_until(&state.readable_until, k, env)
+            && !state.expired_fallbacks.contains_key(*k)
+    }) {
+        return Err(StdError::generic_err(format!(
+            "{} is no longer readable",
+            key
+        )));
+    }
+
+    Ok(expand_env_tokens(
+        drop_unreadable_keys(
+            filter_secret_variables(
+                filter_secret_variables(readable_secret_variables(state)?, scope),
+                keys,
+            ),
+            &state.readable_until,
+            &state.expired_fallbacks,
+            env,
+        ),
+        env,
+    ))
+}
+
+fn is_past_readable_until(readable_until: &BTreeMap<String, u64>, key: &str, env: &Env) -> bool {
+    matches!(readable_until.get(key), Some(height) if *height <= env.block.height)
+}
+
+/// Replaces the keys whose readable until height has been reached with their
+/// fallback, leaving out those without one
+fn drop_unreadable_keys(
+    mut secret_variables: BTreeMap<String, String>,
+    readable_until: &BTreeMap<String, u64>,
+    expired_fallbacks: &BTreeMap<String, String>,
+    env: &Env,
+) -> BTreeMap<String, String> {
+    secret_variables.retain(|key, value| {
+        if !is_past_readable_until(readable_until, key, env) {
+            return true;
+        }
+        match expired_fallbacks.get(key) {
+            Some(fallback) => {
+                *value = fallback.clone();
+                true
+            }
+            None => false,
+        }
+    });
+    secret_variables
+}
+
+/// Computes the replacement of an env token from the block being read at
+type EnvTokenValue = fn(&Env) -> String;
+
+/// Tokens replaced in secret values when they are read, anything else that
+/// looks like a token is returned as stored
+const ENV_TOKENS: &[(&str, EnvTokenValue)] = &[
+    ("${BLOCK_HEIGHT}", |env| env.block.height.to_string()),
+    ("${BLOCK_TIME}", |env| env.block.time.seconds().to_string()),
+];
+
+fn expand_env_tokens(
+    mut secret_variables: BTreeMap<String, String>,
+    env: &Env,
+) -> BTreeMap<String, String> {
+    for value in secret_variables.values_mut() {
+        for (token, expand) in ENV_TOKENS {
+            if value.contains(token) {
+                *value = value.replace(token, &expand(env));
+            }
+        }
+    }
+
+    secret_variables
+}
+
+fn readable_secret_variables(state: &State) -> StdResult<BTreeMap<String, String>> {
+    if state.secret_reference.is_some() {
+        return Err(StdError::generic_err(
+            "Secret variables are stored off-chain, query GetSecretReference instead",
+        ));
+    }
+
+    if checksum(&state.secret_variables)? != state.secret_variables_checksum {
+        return Err(StdError::generic_err(
+            "Secret variables do not match their checksum, storage may be corrupted",
+        ));
+    }
+
+    Ok(state.secret_variables.clone())
+}
+
+/// Asks the reveal oracle about its condition, an oracle that cannot be reached keeps the secrets hidden
+fn check_reveal_condition(deps: Deps, state: &State) -> StdResult<()> {
+    let reveal_oracle = match &state.reveal_oracle {
+        Some(reveal_oracle) => reveal_oracle,
+        None => return Ok(()),
+    };
+
+    let is_condition_met = OracleQueryMsg::IsConditionMet {
+        condition: reveal_oracle.condition.clone(),
+    };
+    let answer: StdResult<OracleQueryAnswer> =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: reveal_oracle.contract_addr.clone(),
+            code_hash: reveal_oracle.code_hash.clone(),
+            msg: to_binary(&is_condition_met)?,
+        }));
+
+    match answer {
+        Ok(OracleQueryAnswer::IsConditionMet { met: true }) => Ok(()),
+        Ok(OracleQueryAnswer::IsConditionMet { met: false }) => {
+            Err(StdError::generic_err("The reveal condition is not met yet"))
+        }
+        Err(_) => Err(StdError::generic_err(
+            "The reveal oracle could not be queried",
+        )),
+    }
+}
+
+fn check_no_read_quorum(state: &State) -> StdResult<()> {
+    if state.read_quorum.is_some() {
+        return Err(StdError::generic_err(
+            "Secret variables require a quorum of viewers, use CosignRead",
+        ));
+    }
+
+    Ok(())
+}
+
+fn query_secret_reference(
+    deps: Deps,
+    env: Env,
+    viewing_key: String,
+    account: String,
+) -> StdResult<SecretReferenceResponse> {
+    let state = authenticate_viewer(deps, &env, &account, &viewing_key)?;
+    check_no_read_quorum(&state)?;
+    check_reveal_condition(deps, &state)?;
+
+    Ok(SecretReferenceResponse {
+        secret_reference: state.secret_reference,
+    })
+}
+
+/// Loads the state after checking that `viewing_key` is the key of `account`,
+/// whether or not it is a viewer. A locked out account fails the same way as a
+/// wrong key, without the key being checked
+fn authenticate_account(
+    deps: Deps,
+    env: &Env,
+    account: &str,
+    viewing_key: &str,
+) -> StdResult<State> {
+    let state = config_read(deps.storage).load()?;
+    let account_raw = deps.api.addr_canonicalize(account)?;
+    check_viewing_key_length(&state, viewing_key)?;
+
+    if check_not_locked_out(deps, env, &account_raw).is_err()
+        || ViewingKey::check(deps.storage, account, viewing_key).is_err()
+    {
+        return Err(StdError::generic_err(format!(
+            "Wrong viewing key for {}, or the account is locked out",
+            account
+        )));
+    }
+
+    Ok(state)
+}
+
+/// Loads the state after checking that `account` is an allowed viewer and `viewing_key` is its key
+fn authenticate_viewer(
+    deps: Deps,
+    env: &Env,
+    account: &str,
+    viewing_key: &str,
+) -> StdResult<State> {
+    let state = config_read(deps.storage).load()?;
+    check_not_paused(&state)?;
+
+    let account_raw = deps.api.addr_canonicalize(account)?;
+    check_not_locked_out(deps, env, &account_raw)?;
+
+    check_viewing_key_length(&state, viewing_key)?;
+    let result = ViewingKey::check(deps.storage, account, viewing_key);
+
+    if result.is_err() || !state.is_active_viewer(&account_raw, env.block.height) {
+        return Err(StdError::generic_err(
+            "Only allowed viewers can query secret variables",
+        ));
+    }
+    check_viewing_key_not_expired(deps, env, &account_raw)?;
+
+    Ok(state)
+}
+
+fn check_viewing_key_not_expired(
+    deps: Deps,
+    env: &Env,
+    account_raw: &CanonicalAddr,
+) -> StdResult<()> {
+    let expiry = viewing_key_expiry_read(deps.storage).may_load(account_raw.as_slice())?;
+    if matches!(expiry, Some(height) if height <= env.block.height) {
+        return Err(StdError::generic_err(
+            "Viewing key has expired, generate a new one",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Reads the secret variables on behalf of the owner of the gating NFT.
+///
+/// The NFT contract checks `nft_viewing_key` for `account` while answering the
+/// `owner_of` query, so a returned owner equal to `account` proves both.
+fn query_secret_variables_with_nft(
+    deps: Deps,
+    env: Env,
+    account: String,
+    nft_viewing_key: String,
+) -> StdResult<BTreeMap<String, String>> {
+    let state = config_read(deps.storage).load()?;
+    check_not_paused(&state)?;
+    check_no_read_quorum(&state)?;
+    check_reveal_condition(deps, &state)?;
+
+    let nft_gate = state
+        .nft_gate
+        .as_ref()
+        .ok_or_else(|| StdError::generic_err("NFT gated access is not configured"))?;
+
+    let owner_of = Snip721QueryMsg::OwnerOf {
+        token_id: nft_gate.token_id.clone(),
+        viewer: Some(Snip721ViewerInfo {
+            address: account.clone(),
+            viewing_key: nft_viewing_key,
+        }),
+    };
+    let answer: StdResult<Snip721QueryAnswer> =
+        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: nft_gate.contract_addr.clone(),
+            code_hash: nft_gate.code_hash.clone(),
+            msg: to_binary(&owner_of)?,
+        }));
+
+    match answer {
+        Ok(Snip721QueryAnswer::OwnerOf { owner }) if owner == account => {
+            secret_variables_within(&state, &env, None, None)
+        }
+        _ => Err(StdError::generic_err(
+            "Only the owner of the gating NFT can query secret variables",
+        )),
+    }
+}
+
+/// Loads the state after checking that `account` is the owner and `viewing_key` is its key
+fn authenticate_owner(deps: Deps, account: &str, viewing_key: &str) -> StdResult<State> {
+    let state = config_read(deps.storage).load()?;
+    let account_raw = deps.api.addr_canonicalize(account)?;
+    check_viewing_key_length(&state, viewing_key)?;
+
+    if ViewingKey::check(deps.storage, account, viewing_key).is_err() || account_raw != state.owner
+    {
+        return Err(StdError::generic_err(
+            "Only the owner can perform this query",
+        ));
+    }
+
+    Ok(state)
+}
+
+fn query_membership_history(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+    start: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<MembershipHistoryResponse> {
+    authenticate_owner(deps, &account, &viewing_key)?;
+
+    let total = membership_log_len_read(deps.storage)
+        .may_load()?
+        .unwrap_or(0);
+    let start = start.unwrap_or(0).min(total);
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as u64;
+    let end = (start + limit).min(total);
+
+    let log = membership_log_read(deps.storage);
+    let changes = (start..end)
+        .map(|index| {
+            let change = log.load(&index.to_be_bytes())?;
+            Ok(MembershipChangeInfo {
+                viewer: deps.api.addr_humanize(&change.viewer)?.to_string(),
+                action: change.action,
+                height: change.height,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(MembershipHistoryResponse { changes, total })
+}
+
+fn query_owner_dashboard(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<OwnerDashboardResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let pending_owner = match &state.pending_owner {
+        Some(pending_owner) => Some(deps.api.addr_humanize(pending_owner)?.to_string()),
+        None => None,
+    };
+
+    Ok(OwnerDashboardResponse {
+        viewer_count: state.allowed_viewers.len() as u32,
+        pending_viewer_count: state.pending_viewers.len() as u32,
+        secret_count: state.secret_variables.len() as u32,
+        last_updated_height: state.last_updated_height,
+        has_staged_secret_variables: state.staged_secret_variables.is_some(),
+        pending_owner,
+        paused: state.paused,
+    })
+}
+
+/// Pages through the secret variables after `start_after`. A page also ends
+/// once it holds `MAX_PAGE_BYTES`, but always returns at least one pair
+fn query_list_secrets(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ListSecretsResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+    let secret_variables = readable_secret_variables(&state)?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as usize;
+
+    let start = match start_after {
+        Some(start_after) => Bound::Excluded(start_after),
+        None => Bound::Unbounded,
+    };
+
+    let mut secrets = vec![];
+    let mut page_bytes = 0;
+    for (key, value) in secret_variables
+        .range((start, Bound::Unbounded))
+        .take(limit)
+    {
+        page_bytes += key.len() + value.len();
+        if !secrets.is_empty() && page_bytes > MAX_PAGE_BYTES {
+            break;
+        }
+        secrets.push((key.clone(), value.clone()));
+    }
+
+    Ok(ListSecretsResponse {
+        secrets,
+        total: secret_variables.len() as u64,
+    })
+}
+
+/// Checks `candidate_key` against every viewer to find whose key it is, e.g. after a leak
+fn query_find_viewing_key_owner(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+    candidate_key: String,
+) -> StdResult<ViewingKeyOwnerResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    for viewer in &state.allowed_viewers {
+        let viewer = deps.api.addr_humanize(&viewer.address)?;
+        if ViewingKey::check(deps.storage, viewer.as_str(), &candidate_key).is_ok() {
+            return Ok(ViewingKeyOwnerResponse {
+                viewer: Some(viewer.to_string()),
+            });
+        }
+    }
+
+    Ok(ViewingKeyOwnerResponse { viewer: None })
+}
+
+fn query_recovery_hint_hash(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<RecoveryHintHashResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    Ok(RecoveryHintHashResponse {
+        recovery_hint_hash: state.recovery_hint_hash,
+    })
+}
+
+fn query_access_counts(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<AccessCountsResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let access_counts = state
+        .allowed_viewers
+        .iter()
+        .map(|v| {
+            let count = state
+                .access_counts
+                .iter()
+                .find(|c| c.viewer == v.address)
+                .map_or(0, |c| c.count);
+            Ok((deps.api.addr_humanize(&v.address)?.to_string(), count))
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(AccessCountsResponse { access_counts })
+}
+
+/// Viewers that are still configured but have a removal scheduled, whether or
+/// not its height has been reached yet
+fn query_pending_removals(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<PendingRemovalsResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let pending_removals = state
+        .scheduled_removals
+        .iter()
+        .map(|r| {
+            Ok((
+                deps.api.addr_humanize(&r.viewer)?.to_string(),
+                r.remove_at_height,
+            ))
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(PendingRemovalsResponse { pending_removals })
+}
+
+fn query_policy_document(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<PolicyDocumentResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let humanize = |address: &CanonicalAddr| -> StdResult<String> {
+        Ok(deps.api.addr_humanize(address)?.to_string())
+    };
+    let viewing_key_expiry = viewing_key_expiry_read(deps.storage);
+    let viewer_key_ttl = viewer_key_ttl_read(deps.storage);
+    let viewer_min_entropy = viewer_min_entropy_read(deps.storage);
+    let viewer_metadata = viewer_metadata_read(deps.storage);
+    let viewers = state
+        .allowed_viewers
+        .iter()
+        .map(|v| {
+            Ok(PolicyViewer {
+                address: humanize(&v.address)?,
+                keys: v.keys.clone(),
+                viewing_key_expires_at: viewing_key_expiry.may_load(v.address.as_slice())?,
+                remove_at_height: state
+                    .scheduled_removals
+                    .iter()
+                    .find(|r| r.viewer == v.address)
+                    .map(|r| r.remove_at_height),
+                viewing_key_ttl_blocks: viewer_key_ttl.may_load(v.address.as_slice())?,
+                min_entropy_length: viewer_min_entropy.may_load(v.address.as_slice())?,
+                metadata: viewer_metadata.may_load(v.address.as_slice())?,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(PolicyDocumentResponse {
+        owner: humanize(&state.owner)?,
+        pending_owner: state.pending_owner.as_ref().map(humanize).transpose()?,
+        governance: state.governance.as_ref().map(humanize).transpose()?,
+        viewers,
+        pending_viewers: state
+            .pending_viewers
+            .iter()
+            .map(humanize)
+            .collect::<StdResult<_>>()?,
+        delegation_gate: state.delegation_gate.clone(),
+        nft_gate: state.nft_gate.clone(),
+        voucher_pubkey: state.voucher_pubkey.clone(),
+        read_quorum: state.read_quorum.clone(),
+        reveal_oracle: state.reveal_oracle.clone(),
+        paused: state.paused,
+        read_once_keys: state.read_once_keys.clone(),
+        readable_until: state.readable_until.clone(),
+        disabled_actions: state.disabled_actions.clone(),
+        immutable: state.immutable,
+        limits: PolicyLimits {
+            min_update_interval_blocks: state.min_update_interval_blocks,
+            max_failed_auths: state.max_failed_auths,
+            lockout_blocks: state.lockout_blocks,
+            max_total_bytes: state.max_total_bytes,
+            auto_pause_threshold: state.auto_pause_threshold,
+            auto_pause_window_blocks: state.auto_pause_window_blocks,
+            viewing_key_length: state.viewing_key_length,
+            viewing_key_ttl_blocks: state.viewing_key_ttl_blocks,
+            max_history_len: state.max_history_len,
+            max_keys_total: state.max_keys_total,
+        },
+    })
+}
+
+fn query_secret_variables_history(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<SecretVariablesHistoryResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    Ok(SecretVariablesHistoryResponse {
+        history: state.secret_variables_history,
+    })
+}
+
+fn query_recent_actions(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<RecentActionsResponse> {
+    authenticate_owner(deps, &account, &viewing_key)?;
+
+    let actions = recent_actions_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default()
+        .into_iter()
+        .rev()
+        .map(|a| {
+            Ok(RecentActionInfo {
+                action: a.action,
+                actor: deps.api.addr_humanize(&a.actor)?.to_string(),
+                height: a.height,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(RecentActionsResponse { actions })
+}
+
+/// Everything that goes into the state fingerprint. New configuration in `State`
+/// belongs here, or in the exclusions listed on `query_state_fingerprint`
+#[derive(Serialize)]
+struct Fingerprint<'a> {
+    owner: &'a CanonicalAddr,
+    pending_owner: &'a Option<CanonicalAddr>,
+    viewers: Vec<FingerprintViewer<'a>>,
+    pending_viewers: Vec<&'a CanonicalAddr>,
+    scheduled_removals: Vec<&'a ScheduledRemoval>,
+    secret_keys: Vec<&'a String>,
+    read_once_keys: Vec<&'a String>,
+    readable_until: &'a BTreeMap<String, u64>,
+    expired_fallback_keys: Vec<&'a String>,
+    public_variables: &'a BTreeMap<String, String>,
+    schema_version: u8,
+    min_update_interval_blocks: u64,
+    max_failed_auths: u32,
+    lockout_blocks: u64,
+    max_total_bytes: Option<u64>,
+    nft_gate: &'a Option<NftGate>,
+    delegation_gate: &'a Option<DelegationGate>,
+    auto_pause_threshold: u32,
+    auto_pause_window_blocks: u64,
+    governance: &'a Option<CanonicalAddr>,
+    read_quorum: &'a Option<ReadQuorum>,
+    viewing_key_length: u32,
+    manifest_hash: &'a Option<Binary>,
+    recovery_hint_hash: &'a Option<Binary>,
+    reveal_oracle: &'a Option<RevealOracle>,
+    disabled_actions: &'a Vec<String>,
+    max_keys_total: Option<u32>,
+    max_history_len: u32,
+    viewing_key_ttl_blocks: Option<u64>,
+    voucher_pubkey: &'a Option<Binary>,
+    immutable: bool,
+}
+
+/// A viewer with the per-viewer overrides kept outside of `State`
+#[derive(Serialize)]
+struct FingerprintViewer<'a> {
+    viewer: &'a AllowedViewer,
+    viewing_key_ttl_blocks: Option<u64>,
+    min_entropy_length: Option<u32>,
+}
+
+/// Hashes the owner, the viewers and the configuration so operators can check
+/// that two deployments match.
+///
+/// Left out are the secret values and what is derived from them, that is the
+/// checksum, the secret reference, staged secret variables, the history and the
+/// values of expired fallbacks, of which only the keys count. Viewer metadata is
+/// left out too, as is anything that changes with ordinary use: the pause and its
+/// reason, the last update height, failed authentications, co-signatures,
+/// consumed read once keys, access counts and the viewing keys themselves. Lists
+/// are sorted so the order entries were added in does not matter.
+fn query_state_fingerprint(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<StateFingerprintResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let mut viewers = state
+        .allowed_viewers
+        .iter()
+        .map(|viewer| {
+            Ok(FingerprintViewer {
+                viewer,
+                viewing_key_ttl_blocks: viewer_key_ttl_read(deps.storage)
+                    .may_load(viewer.address.as_slice())?,
+                min_entropy_length: viewer_min_entropy_read(deps.storage)
+                    .may_load(viewer.address.as_slice())?,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    viewers.sort_by(|a, b| a.viewer.address.cmp(&b.viewer.address));
+    let mut pending_viewers: Vec<&CanonicalAddr> = state.pending_viewers.iter().collect();
+    pending_viewers.sort();
+    let mut scheduled_removals: Vec<&ScheduledRemoval> = state.scheduled_removals.iter().collect();
+    scheduled_removals.sort_by(|a, b| a.viewer.cmp(&b.viewer));
+    let mut read_once_keys: Vec<&String> = state.read_once_keys.iter().collect();
+    read_once_keys.sort();
+    let fingerprint = Fingerprint {
+        owner: &state.owner,
+        pending_owner: &state.pending_owner,
+        viewers,
+        pending_viewers,
+        scheduled_removals,
+        secret_keys: state.secret_variables.keys().collect(),
+        read_once_keys,
+        readable_until: &state.readable_until,
+        expired_fallback_keys: state.expired_fallbacks.keys().collect(),
+        public_variables: &state.public_variables,
+        schema_version: state.schema_version,
+        min_update_interval_blocks: state.min_update_interval_blocks,
+        max_failed_auths: state.max_failed_auths,
+        lockout_blocks: state.lockout_blocks,
+        max_total_bytes: state.max_total_bytes,
+        nft_gate: &state.nft_gate,
+        delegation_gate: &state.delegation_gate,
+        auto_pause_threshold: state.auto_pause_threshold,
+        auto_pause_window_blocks: state.auto_pause_window_blocks,
+        governance: &state.governance,
+        read_quorum: &state.read_quorum,
+        viewing_key_length: state.viewing_key_length,
+        manifest_hash: &state.manifest_hash,
+        recovery_hint_hash: &state.recovery_hint_hash,
+        reveal_oracle: &state.reveal_oracle,
+        disabled_actions: &state.disabled_actions,
+        max_keys_total: state.max_keys_total,
+        max_history_len: state.max_history_len,
+        viewing_key_ttl_blocks: state.viewing_key_ttl_blocks,
+        voucher_pubkey: &state.voucher_pubkey,
+        immutable: state.immutable,
+    };
+
+    Ok(StateFingerprintResponse {
+        fingerprint: Binary::from(sha_256(&to_vec(&fingerprint)?).to_vec()),
+    })
+}
+
+fn query_viewers(deps: Deps, account: String, viewing_key: String) -> StdResult<ViewersResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+
+    let viewers = state
+        .allowed_viewers
+        .iter()
+        .map(|v| Ok(deps.api.addr_humanize(&v.address)?.to_string()))
+        .collect::<StdResult<Vec<String>>>()?;
+
+    Ok(ViewersResponse { viewers })
+}
+
+fn query_viewers_without_keys(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+) -> StdResult<ViewersResponse> {
+    let state = authenticate_owner(deps, &account, &viewing_key)?;
+    let has_key = has_key_read(deps.storage);
+
+    let mut viewers = vec![];
+    for viewer in &state.allowed_viewers {
+        if has_key.may_load(viewer.address.as_slice())?.is_none() {
+            viewers.push(deps.api.addr_humanize(&viewer.address)?.to_string());
+        }
+    }
+
+    Ok(ViewersResponse { viewers })
+}
+
+fn query_manifest_hash(
+    deps: Deps,
+    env: Env,
+    account: String,
+    viewing_key: String,
+) -> StdResult<ManifestHashResponse> {
+    let state = authenticate_viewer(deps, &env, &account, &viewing_key)?;
+
+    Ok(ManifestHashResponse {
+        manifest_hash: state.manifest_hash,
+    })
+}
+
+fn query_viewer_metadata(
+    deps: Deps,
+    account: String,
+    viewing_key: String,
+    viewer: String,
+) -> StdResult<ViewerMetadataResponse> {
+    authenticate_owner(deps, &account, &viewing_key)?;
+
+    let viewer_raw = deps.api.addr_canonicalize(viewer.as_str())?;
+    Ok(ViewerMetadataResponse {
+        metadata: viewer_metadata_read(deps.storage).may_load(viewer_raw.as_slice())?,
+    })
+}
+
+/// Cheap rejection of keys that cannot be valid, before the hash based check
+fn check_viewing_key_length(state: &State, viewing_key: &str) -> StdResult<()> {
+    if viewing_key.len() != state.viewing_key_length as usize {
+        return Err(StdError::generic_err(format!(
+            "Viewing key must be {} characters long, got {}",
+            state.viewing_key_length,
+            viewing_key.len()
+        )));
+    }
+
+    Ok(())
+}
+
+fn check_not_paused(state: &State) -> StdResult<()> {
+    if state.paused {
+        return Err(StdError::generic_err(match &state.pause_reason {
+            Some(reason) => format!(
+                "Secret reads are paused, the owner has to unpause the contract: {}",
+                reason
+            ),
+            None => "Secret reads are paused, the owner has to unpause the contract".to_string(),
+        }));
+    }
+
+    Ok(())
+}
+
+fn check_not_locked_out(deps: Deps, env: &Env, account_raw: &CanonicalAddr) -> StdResult<()> {
+    let record = failed_auths_read(deps.storage).may_load(account_raw.as_slice())?;
+
+    match record.and_then(|record| record.locked_until) {
+        Some(locked_until) if env.block.height < locked_until => {
+            Err(StdError::generic_err(format!(
+                "Account is locked out after too many failed authentications until height {}",
+                locked_until
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Length of the keys from `GenerateViewingKey`, "api_key_" and a base64 encoded hash
+const VIEWING_KEY_LENGTH: u32 = 52;
+
+/// Length of a compressed secp256k1 public key
+const COMPRESSED_PUBKEY_LENGTH: usize = 33;
+
+const DEFAULT_PAGE_LIMIT: u32 = 30;
+const MAX_PAGE_LIMIT: u32 = 100;
+/// Bytes of keys and values after which a ListSecrets page ends early
+const MAX_PAGE_BYTES: usize = 64 * 1024;
+
+/// Flat cost of executing SetSecretVariables, ignoring storage
+const SET_BASE_GAS: u64 = 50_000;
+/// Gas charged by the chain per byte read from storage
+const READ_GAS_PER_BYTE: u64 = 3;
+/// Gas charged by the chain per byte written to storage
+const WRITE_GAS_PER_BYTE: u64 = 30;
+
+/// Heuristic gas estimate for SetSecretVariable.
+///
+/// The whole state is read and written back on every update, so the estimate
+/// is the base cost plus reading the current state and writing it with `value`
+/// in place of the current value of `key`. Signature checks, viewing key work and
+/// chain-level fees are not included, so treat the result as a lower bound.
+fn query_estimate_set_gas(
+    deps: Deps,
+    key: String,
+    value: String,
+) -> StdResult<EstimateGasResponse> {
+    let state = config_read(deps.storage).load()?;
+    let state_len = to_vec(&state)?.len() as u64;
+    let old_len = state
+        .secret_variables
+        .get(&key)
+        .map_or(0, |old| key.len() + old.len()) as u64;
+    let written_len = state_len - old_len + (key.len() + value.len()) as u64;
+
+    Ok(EstimateGasResponse {
+        gas: SET_BASE_GAS + state_len * READ_GAS_PER_BYTE + written_len * WRITE_GAS_PER_BYTE,
+    })
+}
+
+fn query_public_variables(deps: Deps) -> StdResult<BTreeMap<String, String>> {
+    let state = config_read(deps.storage).load()?;
+    Ok(state.public_variables)
+}
+
+fn query_schema_version(deps: Deps) -> StdResult<SchemaVersionResponse> {
+    let state = config_read(deps.storage).load()?;
+    Ok(SchemaVersionResponse {
+        schema_version: state.schema_version,
+    })
+}
+
+fn query_health_check(deps: Deps) -> StdResult<HealthCheckResponse> {
+    let state = config_read(deps.storage).load()?;
+
+    Ok(HealthCheckResponse {
+        paused: state.paused,
+        pause_reason: state.pause_reason,
+    })
+}
+
+fn query_stats(deps: Deps) -> StdResult<StatsResponse> {
+    Ok(StatsResponse {
+        last_grant_height: last_grant_height_read(deps.storage).may_load()?,
+        acknowledged_reads: acknowledged_reads_read(deps.storage)
+            .may_load()?
+            .unwrap_or(0),
+    })
+}
+
+fn query_owner_activity(deps: Deps, env: Env) -> StdResult<OwnerActivityResponse> {
+    let last_owner_activity_height = last_owner_activity_read(deps.storage).load()?;
+
+    Ok(OwnerActivityResponse {
+        last_owner_activity_height,
+        blocks_since_owner_activity: env.block.height.saturating_sub(last_owner_activity_height),
+    })
+}
+
+fn query_block_info(env: Env) -> BlockInfoResponse {
+    BlockInfoResponse {
+        height: env.block.height,
+        time: env.block.time,
+    }
+}
+
+/// A read quorum turns off every mode but cosigning, and NFT reads need a gate
+fn query_auth_modes(deps: Deps) -> StdResult<AuthModesResponse> {
+    let state = config_read(deps.storage).load()?;
+    let no_quorum = state.read_quorum.is_none();
+
+    let mode =
+        |mode: &str, enabled: bool, requires_transaction: bool, cost_hint: &str| AuthModeInfo {
+            mode: mode.to_string(),
+            enabled,
+            requires_transaction,
+            cost_hint: cost_hint.to_string(),
+        };
+
+    Ok(AuthModesResponse {
+        modes: vec![
+            mode(
+                "permit",
+                no_quorum,
+                false,
+                "a free query, the permit is signed off-chain",
+            ),
+            mode(
+                "viewing_key",
+                no_quorum,
+                false,
+                "a free query, after one GenerateViewingKey transaction",
+            ),
+            mode(
+                "nft",
+                no_quorum && state.nft_gate.is_some(),
+                false,
+                "a free query, which also queries the gating NFT contract",
+            ),
+            mode(
+                "delegation",
+                no_quorum && state.delegation_gate.is_some(),
+                false,
+                "a free query with a permit, which also queries the staking module",
+            ),
+            mode(
+                "audited",
+                no_quorum,
+                true,
+                "a GetSecretVariablesAudited transaction per read",
+            ),
+            mode(
+                "cosign",
+                !no_quorum,
+                true,
+                "a CosignRead transaction per read from each viewer of the quorum",
+            ),
+        ],
+    })
+}
+
+fn query_canonicalize_address(deps: Deps, address: String) -> StdResult<CanonicalAddressResponse> {
+    let canonical = deps.api.addr_canonicalize(address.as_str())?;
+    let canonical = canonical
+        .as_slice()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    Ok(CanonicalAddressResponse { canonical })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::msg::{ExecuteAnswer, ImmutableContents};
+    use cosmwasm_std::testing::*;
+    use cosmwasm_std::{
+        coin, from_binary, Addr, Api, Coin, ContractResult, Decimal, FullDelegation, OwnedDeps,
+        SystemResult, Timestamp, Validator,
+    };
+
+    /// Instantiates with every limit left at its default
+    fn default_init_msg() -> InstantiateMsg {
+        InstantiateMsg {
+            prng_seed: b"prng_seed".to_vec().into(),
+            min_update_interval_blocks: None,
+            max_failed_auths: None,
+            lockout_blocks: None,
+            max_total_bytes: None,
+            auto_pause_threshold: None,
+            auto_pause_window_blocks: None,
+            viewing_key_length: None,
+            max_history_len: None,
+            max_keys_total: None,
+            config_json: None,
+            immutable: None,
+        }
+    }
+
+    fn secrets(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        assert_eq!(0, res.messages.len())
+    }
+
+    #[test]
+    fn set_viewers() {
+        let mut deps = mock_dependencies_with_balance(&[Coin {
+            denom: "token".to_string(),
+            amount: Uint128::new(2),
+        }]);
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("anyone", &[]);
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Anyone cannot set viewers");
+    }
+
+    #[test]
+    fn proper_generate_vk() {
+        let mut deps = mock_dependencies_with_balance(&[Coin {
+            denom: "token".to_string(),
+            amount: Uint128::new(2),
+        }]);
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let _key: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+
+        let info = mock_info("hacker", &[]);
+
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg)
+            .expect_err("Hacker cannot generate viewing key");
+    }
+
+    #[test]
+    fn proper_workflow() {
+        let mut deps = mock_dependencies_with_balance(&[Coin {
+            denom: "token".to_string(),
+            amount: Uint128::new(2),
+        }]);
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("secret", "this is a secret")]),
+            trim: None,
+        };
+
+        let info = mock_info("creator", &[]);
+
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let exec_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        let res = query(deps.as_ref(), mock_env(), exec_msg).unwrap();
+
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+
+        assert_eq!(ans, secrets(&[("secret", "this is a secret")]));
+
+        let exec_msg = QueryMsg::GetSecretVariables {
+            viewing_key: "asda".to_string(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        let _res = query(deps.as_ref(), mock_env(), exec_msg)
+            .expect_err("Hacker cannot query secret variables");
+    }
+
+    #[test]
+    fn canonicalize_address() {
+        let deps = mock_dependencies();
+
+        let query_msg = QueryMsg::CanonicalizeAddress {
+            address: "creator".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: CanonicalAddressResponse = from_binary(&res).unwrap();
+
+        // decoding the hex and humanizing it must give back the original address
+        let bytes: Vec<u8> = (0..ans.canonical.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&ans.canonical[i..i + 2], 16).unwrap())
+            .collect();
+        assert_eq!(
+            bytes,
+            deps.api.addr_canonicalize("creator").unwrap().as_slice()
+        );
+        let human = deps.api.addr_humanize(&CanonicalAddr::from(bytes)).unwrap();
+        assert_eq!(human.as_str(), "creator");
+
+        let query_msg = QueryMsg::CanonicalizeAddress {
+            address: "".to_string(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Empty address is invalid");
+    }
+
+    #[test]
+    fn public_variables() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let mut public_variables = BTreeMap::new();
+        public_variables.insert("region".to_string(), "eu-west".to_string());
+
+        let exec_msg = ExecuteMsg::SetPublicVariables {
+            public_variables: public_variables.clone(),
+        };
+        let info = mock_info("anyone", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Anyone cannot set public variables");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        // no viewing key is needed to read public variables
+        let query_msg = QueryMsg::GetPublicVariables {};
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+
+        assert_eq!(ans, public_variables);
+    }
+
+    #[test]
+    fn secret_update_rate_limit() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            min_update_interval_blocks: Some(10),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let mut env = mock_env();
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("secret", "first")]),
+            trim: None,
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        env.block.height += 5;
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("secret", "second")]),
+            trim: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info, exec_msg.clone())
+            .expect_err("Update within the interval is rejected");
+
+        env.block.height += 5;
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), env, info, exec_msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables, secrets(&[("secret", "second")]));
+    }
+
+    #[test]
+    fn generate_vk_without_viewers() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap_err();
+
+        assert_eq!(
+            err,
+            StdError::generic_err("No viewers have been set, viewing keys cannot be generated yet")
+        );
+    }
+
+    #[test]
+    fn set_viewers_keeps_viewing_keys() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key.clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        // removed viewers lose access even though their key is still stored
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        query(deps.as_ref(), mock_env(), query_msg.clone())
+            .expect_err("Removed viewer cannot query secret variables");
+
+        // the old key works again once the viewer is added back
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let _res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    }
+
+    #[test]
+    fn schema_version() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let query_msg = QueryMsg::GetSchemaVersion {};
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: SchemaVersionResponse = from_binary(&res).unwrap();
+
+        assert_eq!(ans.schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn failed_auth_lockout() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_failed_auths: Some(3),
+            lockout_blocks: Some(100),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let report_msg = ExecuteMsg::ReportFailedAuth {
+            account: "viewer1".to_string(),
+            viewing_key: "wrong".to_string(),
+        };
+        let info = mock_info("hacker", &[]);
+        execute(deps.as_mut(), mock_env(), info, report_msg.clone())
+            .expect_err("Anyone cannot report failures for another account");
+
+        let valid_report_msg = ExecuteMsg::ReportFailedAuth {
+            account: "viewer1".to_string(),
+            viewing_key: key.clone(),
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, valid_report_msg)
+            .expect_err("A valid key is not a failure");
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        for _ in 0..2 {
+            let info = mock_info("creator", &[]);
+            let _res = execute(deps.as_mut(), mock_env(), info, report_msg.clone()).unwrap();
+        }
+        let _res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, report_msg).unwrap();
+        query(deps.as_ref(), mock_env(), query_msg.clone())
+            .expect_err("Locked out account cannot query");
+
+        let mut env = mock_env();
+        env.block.height += 100;
+        let _res = query(deps.as_ref(), env, query_msg).unwrap();
+    }
+
+    #[test]
+    fn import_viewers_csv() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::ImportViewersCsv {
+            csv: "viewer1, viewer2\nviewer3,viewer1\n".to_string(),
+            skip_invalid: None,
+        };
+        let info = mock_info("anyone", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Anyone cannot import viewers");
+
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        assert_eq!(res.attributes[0].value, "3");
+        assert_eq!(res.attributes[1].value, "1");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        let expected: Vec<CanonicalAddr> = ["viewer1", "viewer2", "viewer3"]
+            .iter()
+            .map(|v| deps.api.addr_canonicalize(v).unwrap())
+            .collect();
+        assert_eq!(
+            state
+                .allowed_viewers
+                .iter()
+                .map(|v| v.address.clone())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[test]
+    fn storage_budget() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_total_bytes: Some(16),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("key", "0123456789abc")]),
+            trim: None,
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("key", "0123456789abcd")]),
+            trim: None,
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg)
+            .expect_err("Secret larger than the budget is rejected");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables, secrets(&[("key", "0123456789abc")]));
+    }
+
+    #[test]
+    fn nft_gated_access() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("secret", "this is a secret")]),
+            trim: None,
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetNftGate {
+            nft_gate: Some(NftGate {
+                contract_addr: "nft".to_string(),
+                code_hash: "nft_hash".to_string(),
+                token_id: "1".to_string(),
+            }),
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        // token 1 belongs to "holder", who authenticates with the key "nft_key"
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { msg, .. } => match from_binary(msg).unwrap() {
+                Snip721QueryMsg::OwnerOf {
+                    viewer: Some(viewer),
+                    ..
+                } if viewer.viewing_key == "nft_key" => {
+                    let answer = Snip721QueryAnswer::OwnerOf {
+                        owner: "holder".to_string(),
+                    };
+                    SystemResult::Ok(ContractResult::Ok(to_binary(&answer).unwrap()))
+                }
+                _ => SystemResult::Ok(ContractResult::Err("Wrong viewing key".to_string())),
+            },
+            _ => panic!("Unexpected query"),
+        });
+
+        let query_msg = QueryMsg::GetSecretVariablesWithNft {
+            account: "holder".to_string(),
+            nft_viewing_key: "nft_key".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("secret", "this is a secret")]));
+
+        let query_msg = QueryMsg::GetSecretVariablesWithNft {
+            account: "someone".to_string(),
+            nft_viewing_key: "nft_key".to_string(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg)
+            .expect_err("Non-holder cannot query secret variables");
+
+        let query_msg = QueryMsg::GetSecretVariablesWithNft {
+            account: "holder".to_string(),
+            nft_viewing_key: "wrong".to_string(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg)
+            .expect_err("Holder needs a valid NFT viewing key");
+
+        // tamper with the stored value without going through SetSecretVariables
+        config(&mut deps.storage)
+            .update(|mut state| -> StdResult<_> {
+                state.secret_variables = secrets(&[("secret", "this is not the secret")]);
+                Ok(state)
+            })
+            .unwrap();
+
+        let query_msg = QueryMsg::GetSecretVariablesWithNft {
+            account: "holder".to_string(),
+            nft_viewing_key: "nft_key".to_string(),
+        };
+        match query(deps.as_ref(), mock_env(), query_msg) {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "Secret variables do not match their checksum, storage may be corrupted"
+            ),
+            _ => panic!("Checksum mismatch is detected"),
+        }
+    }
+
+    #[test]
+    fn recovery_hint_hash() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let hash = Binary::from(sha_256(b"owner@example.com").to_vec());
+        let exec_msg = ExecuteMsg::SetRecoveryHintHash {
+            recovery_hint_hash: Some(hash.clone()),
+        };
+        let info = mock_info("anyone", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Anyone cannot set the recovery hint hash");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetRecoveryHintHash {
+            account: "creator".to_string(),
+            viewing_key: key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: RecoveryHintHashResponse = from_binary(&res).unwrap();
+        assert_eq!(ans.recovery_hint_hash, Some(hash));
+
+        let query_msg = QueryMsg::GetRecoveryHintHash {
+            account: "creator".to_string(),
+            viewing_key: "wrong".to_string(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Wrong key cannot read the hash");
+    }
+
+    #[cfg(feature = "deterministic-keys")]
+    #[test]
+    fn deterministic_viewing_key() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        assert_eq!(key, "api_key_Pygfnxvid8SbQY9uxxB58KxB11Ft/Nmeq2n8zmTUo/4=");
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let _res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    }
+
+    #[test]
+    fn secret_reference() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let hash = Binary::from(sha_256(b"a very large secret").to_vec());
+        let exec_msg = ExecuteMsg::SetSecretReference {
+            uri: "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string(),
+            hash: hash.clone(),
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretReference {
+            viewing_key: key.clone(),
+            account: "viewer1".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: SecretReferenceResponse = from_binary(&res).unwrap();
+        let secret_reference = ans.secret_reference.unwrap();
+        assert_eq!(
+            secret_reference.uri,
+            "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string()
+        );
+        assert_eq!(secret_reference.hash, hash);
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        query(deps.as_ref(), mock_env(), query_msg)
+            .expect_err("Inline read fails while a reference is stored");
+    }
+
+    #[test]
+    fn secret_variables_checksum() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("secret", "this is a secret")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("secret", "this is a secret")]));
+
+        // tamper with the stored value without going through SetSecretVariables
+        config(&mut deps.storage)
+            .update(|mut state| -> StdResult<_> {
+                state.secret_variables = secrets(&[("secret", "this is not the secret")]);
+                Ok(state)
+            })
+            .unwrap();
+
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Checksum mismatch is detected");
+    }
+
+    #[test]
+    fn auto_pause_on_failed_auths() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            auto_pause_threshold: Some(2),
+            auto_pause_window_blocks: Some(10),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let report_msg = ExecuteMsg::ReportFailedAuth {
+            account: "viewer1".to_string(),
+            viewing_key: "wrong".to_string(),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, report_msg).unwrap();
+
+        // a failure that falls out of the window does not count
+        env.block.height += 20;
+        let info = mock_info("creator", &[]);
+        let report_msg = ExecuteMsg::ReportFailedAuth {
+            account: "viewer2".to_string(),
+            viewing_key: "wrong".to_string(),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, report_msg.clone()).unwrap();
+        let _res = query(deps.as_ref(), env.clone(), query_msg.clone()).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), env.clone(), info, report_msg).unwrap();
+        query(deps.as_ref(), env.clone(), query_msg.clone())
+            .expect_err("Reads are paused after the threshold");
+
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unpause {})
+            .expect_err("Only the owner can unpause");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Unpause {}).unwrap();
+        let _res = query(deps.as_ref(), env, query_msg).unwrap();
+    }
+
+    #[test]
+    fn governance_approved_viewer() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetGovernance {
+            governance: Some("governance".to_string()),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::AddViewer {
+            viewer: "viewer1".to_string(),
+            viewing_key_ttl_blocks: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(res.attributes[0].value, "pending_governance");
+
+        // the viewer cannot use the contract before governance approves it
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Pending viewer cannot generate a viewing key");
+
+        let approve_msg = ExecuteMsg::ApproveViewer {
+            viewer: "viewer1".to_string(),
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, approve_msg.clone())
+            .expect_err("Only governance can approve");
+
+        let info = mock_info("governance", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, approve_msg.clone()).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("governance", &[]);
+        execute(deps.as_mut(), mock_env(), info, approve_msg)
+            .expect_err("Viewer is no longer pending");
+    }
+
+    #[test]
+    fn estimate_set_gas() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let estimate = |value: &str| -> u64 {
+            let query_msg = QueryMsg::EstimateSetGas {
+                key: "secret".to_string(),
+                value: value.to_string(),
+            };
+            let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+            let ans: EstimateGasResponse = from_binary(&res).unwrap();
+            ans.gas
+        };
+
+        let short = estimate("short");
+        let long = estimate("a considerably longer secret value");
+        assert!(short > 0);
+        assert!(long > short);
+    }
+
+    #[test]
+    fn membership_history() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::AddViewer {
+            viewer: "viewer3".to_string(),
+            viewing_key_ttl_blocks: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetMembershipHistory {
+            account: "creator".to_string(),
+            viewing_key: key,
+            start: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: MembershipHistoryResponse = from_binary(&res).unwrap();
+
+        let height = mock_env().block.height;
+        let change = |viewer: &str, action, height| MembershipChangeInfo {
+            viewer: viewer.to_string(),
+            action,
+            height,
+        };
+        assert_eq!(ans.total, 4);
+        assert_eq!(
+            ans.changes,
+            vec![
+                change("viewer1", MembershipAction::Added, height),
+                change("viewer2", MembershipAction::Added, height),
+                change("viewer1", MembershipAction::Removed, height + 1),
+                change("viewer3", MembershipAction::Added, height + 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn init_secret_variables_once() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::InitSecretVariables {
+            secret_variables: secrets(&[("secret", "first")]),
+        };
+        let info = mock_info("anyone", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Anyone cannot init secret variables");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::InitSecretVariables {
+            secret_variables: secrets(&[("secret", "second")]),
+        };
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg)
+            .expect_err("Secret variables can only be initialized once");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables, secrets(&[("secret", "first")]));
+    }
+
+    #[test]
+    fn find_viewing_key_owner() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer2"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            keys.push(match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => key,
+            });
+        }
+
+        let query_msg = QueryMsg::FindViewingKeyOwner {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+            candidate_key: keys[1].clone(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: ViewingKeyOwnerResponse = from_binary(&res).unwrap();
+        assert_eq!(ans.viewer, Some("viewer2".to_string()));
+
+        let query_msg = QueryMsg::FindViewingKeyOwner {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+            candidate_key: "unknown".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: ViewingKeyOwnerResponse = from_binary(&res).unwrap();
+        assert_eq!(ans.viewer, None);
+
+        let query_msg = QueryMsg::FindViewingKeyOwner {
+            account: "viewer2".to_string(),
+            viewing_key: keys[1].clone(),
+            candidate_key: keys[1].clone(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Viewers cannot look up keys");
+    }
+
+    #[test]
+    fn leave_as_viewer() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key.clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let _res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::LeaveAsViewer {},
+        )
+        .unwrap();
+
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Former viewer cannot read");
+        ViewingKey::check(&deps.storage, "viewer1", &key).expect_err("Old key was invalidated");
+
+        let info = mock_info("viewer1", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::LeaveAsViewer {},
+        )
+        .expect_err("Cannot leave twice");
+    }
+
+    #[test]
+    fn read_quorum() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetReadQuorum {
+            read_quorum: Some(ReadQuorum {
+                quorum: 2,
+                window_blocks: 10,
+            }),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for viewer in ["viewer1", "viewer2"] {
+            let info = mock_info(viewer, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            keys.push(match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => key,
+            });
+        }
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[0].clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Single viewer cannot read");
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::CosignRead {
+            viewing_key: keys[0].clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone()).unwrap();
+        assert_eq!(res.data, None);
+
+        // co-signing again does not count twice
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(res.data, None);
+
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::CosignRead {
+            viewing_key: keys[0].clone(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg)
+            .expect_err("Proof must be the caller's key");
+
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::CosignRead {
+            viewing_key: keys[1].clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let value: BTreeMap<String, String> = from_binary(&res.data.unwrap()).unwrap();
+        assert_eq!(value, secrets(&[("SECRET", "1")]));
+
+        // the quorum was used up by that read
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::CosignRead {
+            viewing_key: keys[0].clone(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(res.data, None);
+
+        // co-signatures outside the window expire
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::CosignRead {
+            viewing_key: keys[1].clone(),
+        };
+        let mut env = mock_env();
+        env.block.height += 11;
+        let res = execute(deps.as_mut(), env, info, exec_msg).unwrap();
+        assert_eq!(res.data, None);
+    }
+
+    #[test]
+    fn last_grant_height_stats() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetStats {}).unwrap();
+        let value: StatsResponse = from_binary(&res).unwrap();
+        assert_eq!(value.last_grant_height, None);
+
+        let mut env = mock_env();
+        env.block.height += 5;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::AddViewer {
+            viewer: "viewer1".to_string(),
+            viewing_key_ttl_blocks: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetStats {}).unwrap();
+        let value: StatsResponse = from_binary(&res).unwrap();
+        assert_eq!(value.last_grant_height, Some(env.block.height));
+
+        // removing a viewer is not a grant
+        let mut later = env.clone();
+        later.block.height += 5;
+        let info = mock_info("viewer1", &[]);
+        let _res = execute(deps.as_mut(), later, info, ExecuteMsg::LeaveAsViewer {}).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetStats {}).unwrap();
+        let value: StatsResponse = from_binary(&res).unwrap();
+        assert_eq!(value.last_grant_height, Some(env.block.height));
+    }
+
+    #[test]
+    fn viewer_metadata_hint() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::SetViewerMetadata {
+            viewer: "viewer1".to_string(),
+            metadata: Some("10.0.0.1".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg)
+            .expect_err("Only the owner sets metadata");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerMetadata {
+            viewer: "stranger".to_string(),
+            metadata: Some("10.0.0.1".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only viewers get metadata");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerMetadata {
+            viewer: "viewer1".to_string(),
+            metadata: Some("origin=10.0.0.1".to_string()),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let query_msg = QueryMsg::GetViewerMetadata {
+            account: "creator".to_string(),
+            viewing_key: owner_key.clone(),
+            viewer: "viewer1".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: ViewerMetadataResponse = from_binary(&res).unwrap();
+        assert_eq!(value.metadata, Some("origin=10.0.0.1".to_string()));
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerMetadata {
+            viewer: "viewer1".to_string(),
+            metadata: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: ViewerMetadataResponse = from_binary(&res).unwrap();
+        assert_eq!(value.metadata, None);
+    }
+
+    #[test]
+    fn staged_secret_variables() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("OLD", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::StageSecrets {
+            secret_variables: secrets(&[("NEW", "1")]),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only the owner can stage");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::StageSecrets {
+            secret_variables: secrets(&[("DISCARDED", "1")]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::DiscardStaged {},
+        )
+        .unwrap();
+
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::CommitStaged {})
+            .expect_err("Nothing is staged after a discard");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::StageSecrets {
+            secret_variables: secrets(&[("NEW", "1")]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(value, secrets(&[("OLD", "1")]));
+
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::CommitStaged {})
+            .expect_err("Only the owner can commit");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::CommitStaged {}).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(value, secrets(&[("NEW", "1")]));
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.staged_secret_variables, None);
+    }
+
+    #[test]
+    fn owner_activity() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let start = mock_env().block.height;
+        let mut env = mock_env();
+        env.block.height = start + 10;
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::GetOwnerActivity {}).unwrap();
+        let value: OwnerActivityResponse = from_binary(&res).unwrap();
+        assert_eq!(value.last_owner_activity_height, start);
+        assert_eq!(value.blocks_since_owner_activity, 10);
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        // activity of anyone else does not count
+        let mut later = env.clone();
+        later.block.height += 5;
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let _res = execute(deps.as_mut(), later.clone(), info, exec_msg).unwrap();
+
+        let res = query(deps.as_ref(), later, QueryMsg::GetOwnerActivity {}).unwrap();
+        let value: OwnerActivityResponse = from_binary(&res).unwrap();
+        assert_eq!(value.last_owner_activity_height, env.block.height);
+        assert_eq!(value.blocks_since_owner_activity, 5);
+    }
+
+    #[test]
+    fn viewing_key_length() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+        assert_eq!(key.len(), VIEWING_KEY_LENGTH as usize);
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: "short".to_string(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let err = query(deps.as_ref(), mock_env(), query_msg).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Viewing key must be 52 characters long, got 5")
+        );
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let _res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    }
+
+    #[test]
+    fn manifest_hash() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let manifest_hash = Binary::from(sha_256(b"DB_PASSWORD\nAPI_TOKEN").to_vec());
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::SetManifestHash {
+            manifest_hash: Some(manifest_hash.clone()),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can set the manifest hash");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetManifestHash {
+            account: "viewer1".to_string(),
+            viewing_key: key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: ManifestHashResponse = from_binary(&res).unwrap();
+        assert_eq!(value.manifest_hash, Some(manifest_hash));
+    }
+
+    #[test]
+    fn list_viewers_without_keys() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec![
+                "viewer1".to_string(),
+                "viewer2".to_string(),
+                "viewer3".to_string(),
+            ],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut owner_key = String::new();
+        for account in ["creator", "viewer2"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            if account == "creator" {
+                owner_key = match ans {
+                    ExecuteAnswer::ViewingKeyResponse { key } => key,
+                };
+            }
+        }
+
+        let query_msg = QueryMsg::ListViewersWithoutKeys {
+            account: "creator".to_string(),
+            viewing_key: owner_key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: ViewersResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.viewers,
+            vec!["viewer1".to_string(), "viewer3".to_string()]
+        );
+    }
+
+    #[test]
+    fn state_fingerprint() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetStateFingerprint {
+            account: "creator".to_string(),
+            viewing_key: owner_key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let initial: StateFingerprintResponse = from_binary(&res).unwrap();
+
+        let _res = query(deps.as_ref(), mock_env(), QueryMsg::GetStats {}).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: StateFingerprintResponse = from_binary(&res).unwrap();
+        assert_eq!(value, initial);
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: StateFingerprintResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value, initial,
+            "Secret values are not part of the fingerprint"
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::AddViewer {
+            viewer: "viewer1".to_string(),
+            viewing_key_ttl_blocks: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let with_viewer: StateFingerprintResponse = from_binary(&res).unwrap();
+        assert_ne!(with_viewer, initial);
+
+        // per-viewer overrides count even though they live outside of the state
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerMinEntropy {
+            viewer: "viewer1".to_string(),
+            min_entropy_length: Some(16),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let with_override: StateFingerprintResponse = from_binary(&res).unwrap();
+        assert_ne!(with_override, with_viewer);
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::ScheduleViewerRemoval {
+            viewer: "viewer1".to_string(),
+            remove_at_height: Some(mock_env().block.height + 10),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: StateFingerprintResponse = from_binary(&res).unwrap();
+        assert_ne!(value, with_override);
+    }
+
+    #[test]
+    fn revoke_viewing_keys_for() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec![
+                "viewer1".to_string(),
+                "viewer2".to_string(),
+                "viewer3".to_string(),
+            ],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut queries = vec![];
+        for viewer in ["viewer1", "viewer2"] {
+            let info = mock_info(viewer, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            let key = match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => key,
+            };
+            let query_msg = QueryMsg::GetSecretVariables {
+                viewing_key: key,
+                account: viewer.to_string(),
+                keys: None,
+            };
+            let _res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+            queries.push(query_msg);
+        }
+
+        let exec_msg = ExecuteMsg::RevokeViewingKeysFor {
+            viewers: vec![
+                "viewer1".to_string(),
+                "viewer2".to_string(),
+                "viewer3".to_string(),
+            ],
+        };
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can revoke viewing keys");
+
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(res.attributes[0].value, "2");
+
+        for query_msg in queries {
+            query(deps.as_ref(), mock_env(), query_msg).expect_err("Revoked keys cannot read");
+        }
+    }
+
+    #[test]
+    fn recent_actions_feed() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetRecentActions {
+            account: "creator".to_string(),
+            viewing_key: owner_key,
+        };
+        let res = query(deps.as_ref(), env.clone(), query_msg).unwrap();
+        let value: RecentActionsResponse = from_binary(&res).unwrap();
+        let start = mock_env().block.height;
+        assert_eq!(
+            value.actions,
+            vec![
+                RecentActionInfo {
+                    action: "generate_viewing_key".to_string(),
+                    actor: "creator".to_string(),
+                    height: start + 2,
+                },
+                RecentActionInfo {
+                    action: "generate_viewing_key".to_string(),
+                    actor: "viewer1".to_string(),
+                    height: start + 1,
+                },
+                RecentActionInfo {
+                    action: "set_viewers".to_string(),
+                    actor: "creator".to_string(),
+                    height: start,
+                },
+            ]
+        );
+
+        for _ in 0..RECENT_ACTIONS_CAP {
+            let info = mock_info("creator", &[]);
+            let _res = execute(
+                deps.as_mut(),
+                env.clone(),
+                info,
+                ExecuteMsg::DiscardStaged {},
+            )
+            .unwrap();
+        }
+        let actions = recent_actions_read(&deps.storage).load().unwrap();
+        assert_eq!(actions.len(), RECENT_ACTIONS_CAP);
+        assert!(actions.iter().all(|a| a.action == "discard_staged"));
+    }
+
+    #[test]
+    fn keyed_secret_variables() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        for (key, value) in [
+            ("db_password", "hunter2"),
+            ("api_token", "abc"),
+            ("api_token", "def"),
+        ] {
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::SetSecretVariable {
+                key: key.to_string(),
+                value: value.to_string(),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariable {
+            key: "db_password".to_string(),
+            value: "stolen".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only the owner can write");
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key.clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(
+            ans,
+            secrets(&[("api_token", "def"), ("db_password", "hunter2")])
+        );
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key.clone(),
+            account: "viewer1".to_string(),
+            keys: Some(vec!["api_token".to_string(), "missing".to_string()]),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "def")]));
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RemoveSecretVariable {
+            key: "api_token".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone()).unwrap();
+
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Key is already removed");
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("db_password", "hunter2")]));
+    }
+
+    #[test]
+    fn pause_with_reason() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::HealthCheck {}).unwrap();
+        let value: HealthCheckResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value,
+            HealthCheckResponse {
+                paused: false,
+                pause_reason: None,
+            }
+        );
+
+        let exec_msg = ExecuteMsg::Pause {
+            reason: Some("rotating credentials".to_string()),
+        };
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can pause");
+
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(res.attributes[1].value, "rotating credentials");
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::HealthCheck {}).unwrap();
+        let value: HealthCheckResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value,
+            HealthCheckResponse {
+                paused: true,
+                pause_reason: Some("rotating credentials".to_string()),
+            }
+        );
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Unpause {}).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::HealthCheck {}).unwrap();
+        let value: HealthCheckResponse = from_binary(&res).unwrap();
+        assert_eq!(value.pause_reason, None);
+    }
+
+    #[test]
+    fn query_with_permit() {
+        use secret_toolkit::permit::{PermitParams, PermitSignature, PubKey};
+
+        // signed off-chain for mock_env's contract address by the account below
+        let signer = "secret1sdgykklx0hcp4xssavdn5plq83d5lm32w3uwdx";
+        let permit = |allowed_token: &str, permission: TokenPermissions, signature: &str| Permit {
+            params: PermitParams {
+                allowed_tokens: vec![allowed_token.to_string()],
+                permit_name: "secretvars".to_string(),
+                chain_id: "secret-4".to_string(),
+                permissions: vec![permission],
+            },
+            signature: PermitSignature {
+                pub_key: PubKey {
+                    r#type: "tendermint/PubKeySecp256k1".to_string(),
+                    value: Binary::from_base64("AgHKtaco0KZsxd1f4xO5qDav79u/E7rt54iA2pM/AWNo")
+                        .unwrap(),
+                },
+                signature: Binary::from_base64(signature).unwrap(),
+            },
+        };
+        let owner_signature = "HjgwjiA74IpPQF4t2GqStaZbscA4qy8zbfXOQppBZQMkrs3WQCKWwTxjtee6NbZjYbpDQgW8stk3tl+kdxhdyQ==";
+        let balance_signature = "3DIEg91j5WHYtKws5zQzBw2SiS/oDwhmIy/oiNS0b6QFfRY1W9DDGSCnh4pF44Hitk6DLOfLOBEiECMil67t0Q==";
+
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("api_token", "abc"), ("db_password", "hunter2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let query_msg = QueryMsg::WithPermit {
+            permit: permit("cosmos2contract", TokenPermissions::Owner, owner_signature),
+            query: QueryWithPermit::GetSecretVariables {
+                keys: Some(vec!["api_token".to_string()]),
+            },
+        };
+        query(deps.as_ref(), mock_env(), query_msg.clone())
+            .expect_err("Signer is not a viewer yet");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec![signer.to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "abc")]));
+
+        let query_msg = QueryMsg::WithPermit {
+            permit: permit(
+                "secret1othercontract",
+                TokenPermissions::Owner,
+                owner_signature,
+            ),
+            query: QueryWithPermit::GetSecretVariables { keys: None },
+        };
+        let err = query(deps.as_ref(), mock_env(), query_msg).unwrap_err();
+        assert!(err.to_string().contains("Permit doesn't apply to token"));
+
+        let query_msg = QueryMsg::WithPermit {
+            permit: permit(
+                "cosmos2contract",
+                TokenPermissions::Balance,
+                balance_signature,
+            ),
+            query: QueryWithPermit::GetSecretVariables { keys: None },
+        };
+        let err = query(deps.as_ref(), mock_env(), query_msg).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Permit must grant the owner permission"));
+
+        // a valid signature over different params does not verify
+        let query_msg = QueryMsg::WithPermit {
+            permit: permit(
+                "cosmos2contract",
+                TokenPermissions::Owner,
+                balance_signature,
+            ),
+            query: QueryWithPermit::GetSecretVariables { keys: None },
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Forged permit is rejected");
+
+        // named with an expiry height, one block after and at mock_env's height
+        let expiring = |permit_name: &str, signature: &str| {
+            let mut permit = permit("cosmos2contract", TokenPermissions::Owner, signature);
+            permit.params.permit_name = permit_name.to_string();
+            QueryMsg::WithPermit {
+                permit,
+                query: QueryWithPermit::GetSecretVariables { keys: None },
+            }
+        };
+        let query_msg = expiring(
+            "secretvars@12346",
+            "dbSPPfETbHSaQ+X1oj4sQbnBHPWME5tSe9N4HmxRpAM48JDyI8qk745mUK3sodmw77va0QPxwOwl1aZtkW/JKg==",
+        );
+        let _res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+
+        let query_msg = expiring(
+            "secretvars@12345",
+            "4210kHvECNK+rnRR9cz+XQNVPtWgNFUWloGpTB2NPO5tRYYrqsxkZNecFbfoPjBffT8zouSqz20lcvRCiOZU8w==",
+        );
+        let res = query(deps.as_ref(), mock_env(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Permit expired at height 12345")
+        );
+    }
+
+    #[test]
+    fn transfer_ownership() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("successor", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        )
+        .expect_err("Nothing is pending yet");
+
+        let info = mock_info("successor", &[]);
+        let exec_msg = ExecuteMsg::TransferOwnership {
+            new_owner: "successor".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg)
+            .expect_err("Only the owner can transfer ownership");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::TransferOwnership {
+            new_owner: "typo".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        // a second transfer replaces the pending owner
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::TransferOwnership {
+            new_owner: "successor".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("typo", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        )
+        .expect_err("The replaced pending owner cannot accept");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.owner, deps.api.addr_canonicalize("creator").unwrap());
+
+        let info = mock_info("successor", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        )
+        .unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            state.owner,
+            deps.api.addr_canonicalize("successor").unwrap()
+        );
+        assert_eq!(state.pending_owner, None);
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("The previous owner lost its rights");
+
+        let info = mock_info("successor", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+    }
+
+    #[test]
+    fn set_viewers_invalid_address() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "".to_string()],
+            skip_invalid: None,
+        };
+
+        let info = mock_info("hacker", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, exec_msg.clone()).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Only the owner can set viewers"));
+
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap_err();
+        assert!(err.to_string().contains("Invalid viewer address: \"\""));
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert!(state.allowed_viewers.is_empty());
+    }
+
+    #[test]
+    fn is_access_valid() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_failed_auths: Some(1),
+            lockout_blocks: Some(10),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for viewer in ["viewer1", "viewer2"] {
+            let info = mock_info(viewer, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            keys.push(match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => key,
+            });
+        }
+
+        let validity = |deps: Deps, account: &str, viewing_key: &str| {
+            let query_msg = QueryMsg::IsAccessValid {
+                account: account.to_string(),
+                viewing_key: viewing_key.to_string(),
+            };
+            let res = query(deps, mock_env(), query_msg)?;
+            let ans: AccessValidityResponse = from_binary(&res).unwrap();
+            Ok(ans.reason)
+        };
+
+        assert_eq!(validity(deps.as_ref(), "viewer1", &keys[0]), Ok(None));
+        assert_eq!(
+            validity(deps.as_ref(), "viewer1", &keys[1]),
+            Err(StdError::generic_err(
+                "Wrong viewing key for viewer1, or the account is locked out"
+            ))
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(
+            validity(deps.as_ref(), "viewer1", &keys[0]),
+            Ok(Some(AccessDeniedReason::NotAViewer))
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::Pause { reason: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(
+            validity(deps.as_ref(), "viewer2", &keys[1]),
+            Ok(Some(AccessDeniedReason::Paused))
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::Unpause {};
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        // the right key of a locked out account fails like a wrong one
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::ReportFailedAuth {
+            account: "viewer2".to_string(),
+            viewing_key: "wrong".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(
+            validity(deps.as_ref(), "viewer2", &keys[1]),
+            Err(StdError::generic_err(
+                "Wrong viewing key for viewer2, or the account is locked out"
+            ))
+        );
+    }
+
+    #[test]
+    fn viewer_scope() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("api_token", "abc"), ("db_password", "hunter2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::SetViewerScope {
+            viewer: "viewer1".to_string(),
+            keys: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only the owner scopes");
+
+        // scoping a new address adds it as a viewer
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerScope {
+            viewer: "viewer2".to_string(),
+            keys: Some(vec!["api_token".to_string()]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for viewer in ["viewer1", "viewer2", "stranger"] {
+            let info = mock_info(viewer, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+            if viewer == "stranger" {
+                res.expect_err("Strangers are not viewers");
+                break;
+            }
+            let ans: ExecuteAnswer = from_binary(&res.unwrap().data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[0].clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(
+            ans,
+            secrets(&[("api_token", "abc"), ("db_password", "hunter2")])
+        );
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[1].clone(),
+            account: "viewer2".to_string(),
+            keys: Some(vec!["api_token".to_string(), "db_password".to_string()]),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "abc")]));
+
+        // SetViewers keeps the scopes of viewers that stay
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer2".to_string(), "viewer3".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[1].clone(),
+            account: "viewer2".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "abc")]));
+
+        let state = config_read(&deps.storage).load().unwrap();
+        let viewer3 = deps.api.addr_canonicalize("viewer3").unwrap();
+        assert_eq!(
+            state.allowed_viewers.iter().find(|v| v.address == viewer3),
+            Some(&AllowedViewer {
+                address: viewer3.clone(),
+                keys: None,
+            })
+        );
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[0].clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Only allowed viewers can query secret variables")
+        );
+    }
+
+    #[test]
+    fn get_viewers() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetViewers {
+            account: "creator".to_string(),
+            viewing_key: "wrong_key".to_string(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("A valid viewing key is required");
+
+        let query_msg = QueryMsg::GetViewers {
+            account: "creator".to_string(),
+            viewing_key: owner_key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: ViewersResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.viewers,
+            vec!["viewer1".to_string(), "viewer2".to_string()]
+        );
+    }
+
+    #[test]
+    fn get_viewers_rejects_viewers() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetViewers {
+            account: "viewer1".to_string(),
+            viewing_key: key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Only the owner can perform this query")
+        );
+    }
+
+    #[test]
+    fn viewing_key_ttl() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewingKeyTtl {
+            viewing_key_ttl_blocks: Some(0),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("TTL must be positive");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewingKeyTtl {
+            viewing_key_ttl_blocks: Some(10),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        for (viewer, viewing_key_ttl_blocks) in [("viewer1", None), ("viewer2", Some(100))] {
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::AddViewer {
+                viewer: viewer.to_string(),
+                viewing_key_ttl_blocks,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let mut keys = vec![];
+        for viewer in ["viewer1", "viewer2"] {
+            let info = mock_info(viewer, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let mut env = mock_env();
+        env.block.height += 9;
+        for (viewer, key) in ["viewer1", "viewer2"].iter().zip(&keys) {
+            let query_msg = QueryMsg::GetSecretVariables {
+                viewing_key: key.clone(),
+                account: viewer.to_string(),
+                keys: None,
+            };
+            query(deps.as_ref(), env.clone(), query_msg).unwrap();
+        }
+
+        // viewer1 inherits the 10 block default, viewer2 overrides it with 100
+        env.block.height += 1;
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[0].clone(),
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), env.clone(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Viewing key has expired, generate a new one")
+        );
+
+        let query_msg = QueryMsg::IsAccessValid {
+            account: "viewer1".to_string(),
+            viewing_key: keys[0].clone(),
+        };
+        let res = query(deps.as_ref(), env.clone(), query_msg).unwrap();
+        let value: AccessValidityResponse = from_binary(&res).unwrap();
+        assert_eq!(value.reason, Some(AccessDeniedReason::ExpiredViewingKey));
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[1].clone(),
+            account: "viewer2".to_string(),
+            keys: None,
+        };
+        query(deps.as_ref(), env.clone(), query_msg).unwrap();
+
+        env.block.height += 90;
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: keys[1].clone(),
+            account: "viewer2".to_string(),
+            keys: None,
+        };
+        query(deps.as_ref(), env, query_msg).expect_err("viewer2's key expired as well");
+    }
+
+    #[test]
+    fn revoke_viewer() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::RevokeViewer {
+            viewer: "viewer1".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only the owner revokes");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RevokeViewer {
+            viewer: "viewer1".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        let viewer1 = deps.api.addr_canonicalize("viewer1").unwrap();
+        assert!(!state.is_allowed_viewer(&viewer1));
+        assert_eq!(state.allowed_viewers.len(), 1);
+        ViewingKey::check(&deps.storage, "viewer1", &key).expect_err("Old key was invalidated");
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Revoked viewer cannot read");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RevokeViewer {
+            viewer: "viewer1".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("viewer1 is not an allowed viewer")
+        );
+    }
+
+    #[test]
+    fn read_once_secret() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("api_token", "abc"), ("initial_password", "hunter2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetReadOnce {
+            key: "missing".to_string(),
+            read_once: true,
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only stored keys");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetReadOnce {
+            key: "initial_password".to_string(),
+            read_once: true,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key.clone(),
+            account: "viewer1".to_string(),
+            keys: Some(vec!["initial_password".to_string()]),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("initial_password", "hunter2")]));
+
+        let info = mock_info("stranger", &[]);
+        let exec_msg = ExecuteMsg::AcknowledgeRead {
+            key: "initial_password".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only viewers acknowledge");
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::AcknowledgeRead {
+            key: "api_token".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Not a read once secret");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::ScheduleViewerRemoval {
+            viewer: "viewer1".to_string(),
+            remove_at_height: Some(mock_env().block.height + 10),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.height += 10;
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::AcknowledgeRead {
+            key: "initial_password".to_string(),
+        };
+        let res = execute(deps.as_mut(), env, info, exec_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Only allowed viewers can acknowledge reads")
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::Pause { reason: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::AcknowledgeRead {
+            key: "initial_password".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Secret reads are paused, the owner has to unpause the contract")
+        );
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::Unpause {};
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::AcknowledgeRead {
+            key: "initial_password".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err(
+                "initial_password was a read once secret and has already been read"
+            )
+        );
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "abc")]));
+    }
+
+    #[test]
+    fn audited_reads() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer1"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let query_msg = QueryMsg::GetAccessCounts {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: AccessCountsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.access_counts,
+            vec![("viewer1".to_string(), 0), ("viewer2".to_string(), 0)]
+        );
+
+        for _ in 0..2 {
+            let info = mock_info("viewer1", &[]);
+            let exec_msg = ExecuteMsg::GetSecretVariablesAudited {
+                viewing_key: keys[1].clone(),
+                keys: None,
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: BTreeMap<String, String> = from_binary(&res.data.unwrap()).unwrap();
+            assert_eq!(ans, secrets(&[("SECRET", "1")]));
+        }
+
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::GetSecretVariablesAudited {
+            viewing_key: keys[1].clone(),
+            keys: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Not viewer2's key");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GetSecretVariablesAudited {
+            viewing_key: keys[0].clone(),
+            keys: None,
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only viewers read");
+
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: AccessCountsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.access_counts,
+            vec![("viewer1".to_string(), 2), ("viewer2".to_string(), 0)]
+        );
+
+        // a viewer that is removed and added again starts over
+        for viewers in [vec!["viewer2"], vec!["viewer1", "viewer2"]] {
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::SetViewers {
+                viewers: viewers.into_iter().map(String::from).collect(),
+                skip_invalid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: AccessCountsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.access_counts,
+            vec![("viewer1".to_string(), 0), ("viewer2".to_string(), 0)]
+        );
+
+        let query_msg = QueryMsg::GetAccessCounts {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Only the owner sees the counts");
+    }
+
+    #[test]
+    fn verify_secret_matches() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("db_password", "hunter2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        for (candidate, matches) in [("hunter2", true), ("hunter3", false)] {
+            let query_msg = QueryMsg::VerifySecretMatches {
+                account: "viewer1".to_string(),
+                viewing_key: key.clone(),
+                key: "db_password".to_string(),
+                candidate_hash: Binary::from(sha_256(candidate.as_bytes()).to_vec()),
+            };
+            let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+            let value: SecretMatchesResponse = from_binary(&res).unwrap();
+            assert_eq!(value.matches, matches);
+        }
+
+        let query_msg = QueryMsg::VerifySecretMatches {
+            account: "viewer1".to_string(),
+            viewing_key: key,
+            key: "missing".to_string(),
+            candidate_hash: Binary::from(sha_256(b"hunter2").to_vec()),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("No such secret variable");
+    }
+
+    #[test]
+    fn secret_variables_history() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_history_len: Some(2),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let mut env = mock_env();
+        for value in ["1", "2", "3"] {
+            env.block.height += 1;
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::SetSecretVariables {
+                secret_variables: secrets(&[("SECRET", value)]),
+                trim: None,
+            };
+            let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+        }
+
+        // the empty variables replaced first were evicted by the cap
+        let query_msg = QueryMsg::GetSecretVariablesHistory {
+            account: "creator".to_string(),
+            viewing_key: owner_key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: SecretVariablesHistoryResponse = from_binary(&res).unwrap();
+        let height = mock_env().block.height;
+        assert_eq!(
+            value.history,
+            vec![
+                (height + 2, secrets(&[("SECRET", "1")])),
+                (height + 3, secrets(&[("SECRET", "2")])),
+            ]
+        );
+
+        for steps in [0, 3] {
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::RollbackSecretVariables { steps };
+            let res = execute(deps.as_mut(), env.clone(), info, exec_msg);
+            assert_eq!(
+                res.unwrap_err(),
+                StdError::generic_err(format!(
+                    "Cannot roll back {} steps, the history holds 2 versions",
+                    steps
+                ))
+            );
+        }
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::RollbackSecretVariables { steps: 1 };
+        execute(deps.as_mut(), env.clone(), info, exec_msg).expect_err("Only the owner rolls back");
+
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RollbackSecretVariables { steps: 2 };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables, secrets(&[("SECRET", "1")]));
+
+        // the rolled back variables can be restored in turn
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: SecretVariablesHistoryResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.history,
+            vec![
+                (height + 3, secrets(&[("SECRET", "2")])),
+                (height + 4, secrets(&[("SECRET", "3")])),
+            ]
+        );
+
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RollbackSecretVariables { steps: 1 };
+        let _res = execute(deps.as_mut(), env, info, exec_msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables, secrets(&[("SECRET", "3")]));
+    }
+
+    #[test]
+    fn redeem_voucher() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.chain_id = "secret-4".to_string();
+        env.contract.address = Addr::unchecked("secret1vars");
+
+        // signed over sha256 of
+        // {"chain_id":"secret-4","contract":"secret1vars","viewer":"viewer1","expires_at_height":12400}
+        let voucher = Voucher {
+            viewer: "viewer1".to_string(),
+            expires_at_height: 12400,
+        };
+        let redeem = |signature: &str| ExecuteMsg::RedeemVoucher {
+            voucher: voucher.clone(),
+            signature: Binary::from_base64(signature).unwrap(),
+        };
+        let signature = "vi5W1EylEr8BN7LBrR4Trzc86dpT26RWpXdyP4whxUQD6PVTh6RuPFL/4/U4F9CjQHr6uDkplFm1Pd9maR8hiA==";
+        let forged = "Ey+VqZmXsdTRCpdc+EbRW3zUjN40qVSQJBM8f59bgFQRRL6ndcUvQiF6FiWMllm2cxu0OeVjbu+PLRND5W8Dbg==";
+        // the same voucher signed by the right key for "secret1othervars"
+        let other_contract = "dSd72+FEK5O4b3kaIGT94IvacJxN/rvrwpIv6M391UQrtSfV/zLguG4fG9nxTNgsHHwbq8TrjhIfz6oyevsoLg==";
+
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, redeem(signature));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Vouchers are not accepted")
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetVoucherPubkey {
+            pubkey: Some(
+                Binary::from_base64("AgHKtaco0KZsxd1f4xO5qDav79u/E7rt54iA2pM/AWNo").unwrap(),
+            ),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, redeem(forged));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Voucher was not signed by the owner")
+        );
+
+        let info = mock_info("viewer2", &[]);
+        execute(deps.as_mut(), env.clone(), info, redeem(signature))
+            .expect_err("The voucher names viewer1");
+
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, redeem(other_contract));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Voucher was not signed by the owner")
+        );
+
+        let mut expired_env = env.clone();
+        expired_env.block.height = 12400;
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), expired_env, info, redeem(signature));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Voucher has expired")
+        );
+
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, redeem(signature)).unwrap();
+        assert_eq!(res.attributes[0].value, "added");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        let viewer1 = deps.api.addr_canonicalize("viewer1").unwrap();
+        assert!(state.is_allowed_viewer(&viewer1));
+
+        let info = mock_info("viewer1", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::LeaveAsViewer {},
+        )
+        .unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, redeem(signature));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Voucher was already redeemed")
+        );
+    }
+
+    #[test]
+    fn block_info() {
+        let deps = mock_dependencies();
+
+        let mut env = mock_env();
+        env.block.height = 54321;
+        env.block.time = Timestamp::from_seconds(1_700_000_000);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::GetBlockInfo {}).unwrap();
+        let value: BlockInfoResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value,
+            BlockInfoResponse {
+                height: env.block.height,
+                time: env.block.time,
+            }
+        );
+    }
+
+    #[test]
+    fn reveal_oracle() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let reveal_oracle = RevealOracle {
+            contract_addr: "oracle".to_string(),
+            code_hash: "oracle_hash".to_string(),
+            condition: "launch".to_string(),
+        };
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::SetRevealOracle {
+            reveal_oracle: Some(reveal_oracle.clone()),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only the owner sets it");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetRevealOracle {
+            reveal_oracle: Some(reveal_oracle),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+
+        for met in [false, true] {
+            deps.querier.update_wasm(move |query| match query {
+                WasmQuery::Smart { msg, .. } => match from_binary(msg).unwrap() {
+                    OracleQueryMsg::IsConditionMet { condition } if condition == "launch" => {
+                        let answer = OracleQueryAnswer::IsConditionMet { met };
+                        SystemResult::Ok(ContractResult::Ok(to_binary(&answer).unwrap()))
+                    }
+                    _ => SystemResult::Ok(ContractResult::Err("Unknown condition".to_string())),
+                },
+                _ => panic!("Unexpected query"),
+            });
+
+            let res = query(deps.as_ref(), mock_env(), query_msg.clone());
+            if met {
+                let ans: BTreeMap<String, String> = from_binary(&res.unwrap()).unwrap();
+                assert_eq!(ans, secrets(&[("SECRET", "1")]));
+            } else {
+                assert_eq!(
+                    res.unwrap_err(),
+                    StdError::generic_err("The reveal condition is not met yet")
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn list_secrets() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[
+                ("a", "1"),
+                ("b", "2"),
+                ("c", "3"),
+                ("d", "4"),
+                ("e", "5"),
+            ]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let mut pages = vec![];
+        let mut start_after = None;
+        loop {
+            let query_msg = QueryMsg::ListSecrets {
+                account: "creator".to_string(),
+                viewing_key: owner_key.clone(),
+                start_after: start_after.clone(),
+                limit: Some(2),
+            };
+            let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+            let value: ListSecretsResponse = from_binary(&res).unwrap();
+            assert_eq!(value.total, 5);
+            if value.secrets.is_empty() {
+                break;
+            }
+            start_after = value.secrets.last().map(|(key, _)| key.clone());
+            pages.push(value.secrets);
+        }
+
+        let pair = |key: &str, value: &str| (key.to_string(), value.to_string());
+        assert_eq!(
+            pages,
+            vec![
+                vec![pair("a", "1"), pair("b", "2")],
+                vec![pair("c", "3"), pair("d", "4")],
+                vec![pair("e", "5")],
+            ]
+        );
+
+        let query_msg = QueryMsg::ListSecrets {
+            account: "creator".to_string(),
+            viewing_key: "wrong_key".to_string(),
+            start_after: None,
+            limit: None,
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Only the owner lists secrets");
+    }
+
+    #[test]
+    fn disable_actions() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let set_viewers = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, set_viewers.clone()).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::DisableActions {
+            actions: vec!["set_viewers".to_string()],
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Only the owner disables");
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::DisableActions {
+            actions: vec!["add_viewer".to_string(), "set_viewer".to_string()],
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Unknown action: set_viewer")
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::DisableActions {
+            actions: vec!["set_viewers".to_string()],
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, set_viewers);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("set_viewers has been disabled")
+        );
+
+        // other messages keep working
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::AddViewer {
+            viewer: "viewer2".to_string(),
+            viewing_key_ttl_blocks: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+    }
+
+    #[test]
+    fn self_test() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, ExecuteMsg::SelfTest {})
+            .expect_err("Only the owner runs the self test");
+
+        let state_before = config_read(&deps.storage).load().unwrap();
+        let actions_before = recent_actions_read(&deps.storage).load().unwrap();
+
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::SelfTest {}).unwrap();
+        let report: SelfTestResponse = from_binary(&res.data.unwrap()).unwrap();
+        assert!(report.ok);
+        assert!(report.checks.iter().all(|c| c.ok));
+        assert!(report
+            .checks
+            .iter()
+            .any(|c| c.name == "no_duplicate_viewers"));
+
+        assert_eq!(config_read(&deps.storage).load().unwrap(), state_before);
+        assert_eq!(
+            recent_actions_read(&deps.storage).load().unwrap(),
+            actions_before
+        );
+    }
+
+    #[test]
+    fn env_tokens() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[
+                ("nonce", "height-${BLOCK_HEIGHT}"),
+                ("unknown", "${HOME}"),
+            ]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let mut env = mock_env();
+        env.block.height = 20000;
+        let query_msg = QueryMsg::GetSecretVariables {
+            viewing_key: key,
+            account: "viewer1".to_string(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), env, query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(
+            ans,
+            secrets(&[("nonce", "height-20000"), ("unknown", "${HOME}")])
+        );
+
+        // the stored value keeps the token
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables["nonce"], "height-${BLOCK_HEIGHT}");
+    }
+
+    #[test]
+    fn owner_dashboard() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let messages = vec![
+            ExecuteMsg::SetViewers {
+                viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+                skip_invalid: None,
+            },
+            ExecuteMsg::SetSecretVariables {
+                secret_variables: secrets(&[("a", "1"), ("b", "2"), ("c", "3")]),
+                trim: None,
+            },
+            ExecuteMsg::StageSecrets {
+                secret_variables: secrets(&[("a", "4")]),
+            },
+            ExecuteMsg::SetGovernance {
+                governance: Some("governance".to_string()),
+            },
+            ExecuteMsg::AddViewer {
+                viewer: "viewer3".to_string(),
+                viewing_key_ttl_blocks: None,
+            },
+            ExecuteMsg::TransferOwnership {
+                new_owner: "successor".to_string(),
+            },
+            ExecuteMsg::Pause {
+                reason: Some("maintenance".to_string()),
+            },
+        ];
+        for exec_msg in messages {
+            let info = mock_info("creator", &[]);
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let owner_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::OwnerDashboard {
+            account: "creator".to_string(),
+            viewing_key: owner_key,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: OwnerDashboardResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value,
+            OwnerDashboardResponse {
+                viewer_count: 2,
+                pending_viewer_count: 1,
+                secret_count: 3,
+                last_updated_height: Some(mock_env().block.height),
+                has_staged_secret_variables: true,
+                pending_owner: Some("successor".to_string()),
+                paused: true,
+            }
+        );
+
+        let query_msg = QueryMsg::OwnerDashboard {
+            account: "viewer1".to_string(),
+            viewing_key: "wrong_key".to_string(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Only the owner sees the dashboard");
+    }
+
+    #[test]
+    fn bulk_viewer_operations_can_skip_invalid_addresses() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let viewers = vec![
+            "viewer1".to_string(),
+            "x".to_string(),
+            "viewer2".to_string(),
+        ];
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: viewers.clone(),
+            skip_invalid: None,
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), exec_msg)
+            .expect_err("An invalid address rejects the batch by default");
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers,
+            skip_invalid: Some(true),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), exec_msg).unwrap();
+        assert_eq!(res.attributes[0].key, "invalid");
+        assert_eq!(res.attributes[0].value, "x");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        let expected: Vec<CanonicalAddr> = ["viewer1", "viewer2"]
+            .iter()
+            .map(|v| deps.api.addr_canonicalize(v).unwrap())
+            .collect();
+        assert_eq!(
+            state
+                .allowed_viewers
+                .iter()
+                .map(|v| v.address.clone())
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        let exec_msg = ExecuteMsg::ImportViewersCsv {
+            csv: "viewer3,y\nviewer1".to_string(),
+            skip_invalid: Some(true),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_eq!(res.attributes[0].value, "1");
+        assert_eq!(res.attributes[1].value, "1");
+        assert_eq!(res.attributes[2].key, "invalid");
+        assert_eq!(res.attributes[2].value, "y");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.allowed_viewers.len(), 3);
+    }
+
+    #[test]
+    fn set_secret_variables_can_trim_values() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("a", "  padded\n"), ("b", "kept")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), exec_msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            state.secret_variables,
+            secrets(&[("a", "  padded\n"), ("b", "kept")])
+        );
+
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("a", "  padded\n"), ("b", "kept")]),
+            trim: Some(true),
+        };
+        let mut env = mock_env();
+        env.block.height += 1;
+        let _res = execute(deps.as_mut(), env, info, exec_msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            state.secret_variables,
+            secrets(&[("a", "padded"), ("b", "kept")])
+        );
+    }
+
+    #[test]
+    fn scheduled_viewer_removal() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("SECRET", "1")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer1"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let remove_at_height = mock_env().block.height + 10;
+        let exec_msg = ExecuteMsg::ScheduleViewerRemoval {
+            viewer: "viewer1".to_string(),
+            remove_at_height: Some(remove_at_height),
+        };
+        let info = mock_info("viewer2", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can schedule removals");
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let query_msg = QueryMsg::GetPendingRemovals {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: PendingRemovalsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.pending_removals,
+            vec![("viewer1".to_string(), remove_at_height)]
+        );
+        let query_msg_as_viewer = QueryMsg::GetPendingRemovals {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg_as_viewer).expect_err("Owner only");
+
+        // still a viewer during the grace period, not after it
+        let secrets_query = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+            keys: None,
+        };
+        let _res = query(deps.as_ref(), mock_env(), secrets_query.clone()).unwrap();
+        let mut env = mock_env();
+        env.block.height = remove_at_height;
+        query(deps.as_ref(), env, secrets_query).expect_err("Removal took effect");
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert!(state.is_allowed_viewer(&deps.api.addr_canonicalize("viewer1").unwrap()));
+
+        let exec_msg = ExecuteMsg::ScheduleViewerRemoval {
+            viewer: "viewer1".to_string(),
+            remove_at_height: None,
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: PendingRemovalsResponse = from_binary(&res).unwrap();
+        assert!(value.pending_removals.is_empty());
+    }
+
+    #[test]
+    fn instantiate_from_config_json() {
+        let mut deps = mock_dependencies();
+        let init_msg = InstantiateMsg {
+            config_json: Some(
+                r#"{"max_failed_auths":3,"lockout_blocks":20,"max_history_len":1}"#.to_string(),
+            ),
+            ..default_init_msg()
+        };
+
+        let info = mock_info("creator", &[]);
+        let mut conflicting_msg = init_msg.clone();
+        conflicting_msg.lockout_blocks = Some(10);
+        let res = instantiate(deps.as_mut(), mock_env(), info.clone(), conflicting_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("lockout_blocks is set both in the message and in config_json")
+        );
+
+        let mut unknown_field_msg = init_msg.clone();
+        unknown_field_msg.config_json = Some(r#"{"lockout_block":20}"#.to_string());
+        instantiate(deps.as_mut(), mock_env(), info.clone(), unknown_field_msg)
+            .expect_err("Unknown fields are rejected");
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.max_failed_auths, 3);
+        assert_eq!(state.lockout_blocks, 20);
+        assert_eq!(state.max_history_len, 1);
+        assert_eq!(state.min_update_interval_blocks, 0);
+        assert_eq!(state.viewing_key_length, VIEWING_KEY_LENGTH);
+    }
+
+    #[test]
+    fn readable_until() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("launch_code", "1234"), ("api_token", "abc")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer1"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let readable_until = mock_env().block.height + 5;
+        let exec_msg = ExecuteMsg::SetReadableUntil {
+            key: "launch_code".to_string(),
+            readable_until: Some(readable_until),
+            fallback: None,
+        };
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can set readable until");
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans.len(), 2);
+
+        let mut env = mock_env();
+        env.block.height = readable_until;
+        let res = query(deps.as_ref(), env.clone(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "abc")]));
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+            keys: Some(vec!["launch_code".to_string()]),
+        };
+        let res = query(deps.as_ref(), env.clone(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("launch_code is no longer readable")
+        );
+
+        // the owner still sees and manages the key
+        let query_msg = QueryMsg::ListSecrets {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+            start_after: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), env.clone(), query_msg).unwrap();
+        let value: ListSecretsResponse = from_binary(&res).unwrap();
+        assert_eq!(value.total, 2);
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RemoveSecretVariable {
+            key: "launch_code".to_string(),
+        };
+        let _res = execute(deps.as_mut(), env, info, exec_msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert!(state.readable_until.is_empty());
+    }
+
+    #[test]
+    fn acknowledged_reads_stats() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetStats {}).unwrap();
+        let value: StatsResponse = from_binary(&res).unwrap();
+        assert_eq!(value.acknowledged_reads, 0);
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("a", "1"), ("b", "2"), ("c", "3")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        for key in ["a", "b", "c"] {
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::SetReadOnce {
+                key: key.to_string(),
+                read_once: true,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        for (viewer, key) in [("viewer1", "a"), ("viewer2", "b"), ("viewer1", "c")] {
+            let info = mock_info(viewer, &[]);
+            let exec_msg = ExecuteMsg::AcknowledgeRead {
+                key: key.to_string(),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let info = mock_info("viewer2", &[]);
+        let exec_msg = ExecuteMsg::AcknowledgeRead {
+            key: "a".to_string(),
+        };
+        execute(deps.as_mut(), mock_env(), info, exec_msg).expect_err("Already acknowledged");
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetStats {}).unwrap();
+        let value: StatsResponse = from_binary(&res).unwrap();
+        assert_eq!(value.acknowledged_reads, 3);
+    }
+
+    #[test]
+    fn viewer_min_entropy_length() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::SetViewerMinEntropy {
+            viewer: "viewer1".to_string(),
+            min_entropy_length: Some(32),
+        };
+        let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can set entropy requirements");
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let short_entropy = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let info = mock_info("viewer1", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, short_entropy.clone());
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Entropy has to be at least 32 bytes long for this viewer")
+        );
+
+        let info = mock_info("viewer2", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, short_entropy).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "a much longer entropy string of 40 bytes".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+    }
+
+    #[test]
+    fn allowed_queries_with_permit() {
+        use secret_toolkit::permit::{PermitParams, PermitSignature, PubKey};
+
+        // the signer and signature of the query_with_permit test
+        let signer = "secret1sdgykklx0hcp4xssavdn5plq83d5lm32w3uwdx";
+        let query_msg = QueryMsg::WithPermit {
+            permit: Permit {
+                params: PermitParams {
+                    allowed_tokens: vec!["cosmos2contract".to_string()],
+                    permit_name: "secretvars".to_string(),
+                    chain_id: "secret-4".to_string(),
+                    permissions: vec![TokenPermissions::Owner],
+                },
+                signature: PermitSignature {
+                    pub_key: PubKey {
+                        r#type: "tendermint/PubKeySecp256k1".to_string(),
+                        value: Binary::from_base64("AgHKtaco0KZsxd1f4xO5qDav79u/E7rt54iA2pM/AWNo")
+                            .unwrap(),
+                    },
+                    signature: Binary::from_base64("HjgwjiA74IpPQF4t2GqStaZbscA4qy8zbfXOQppBZQMkrs3WQCKWwTxjtee6NbZjYbpDQgW8stk3tl+kdxhdyQ==").unwrap(),
+                },
+            },
+            query: QueryWithPermit::GetAllowedQueries {},
+        };
+        let init_msg = default_init_msg();
+
+        // the signer owns this contract
+        let mut deps = mock_dependencies();
+        let info = mock_info(signer, &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg.clone()).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let owner_queries: AllowedQueriesResponse = from_binary(&res).unwrap();
+        assert!(owner_queries.queries.contains(&"list_secrets".to_string()));
+        assert!(!owner_queries
+            .queries
+            .contains(&"get_secret_variables".to_string()));
+
+        // and is a viewer of this one
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let outsider_queries: AllowedQueriesResponse = from_binary(&res).unwrap();
+        assert!(!outsider_queries
+            .queries
+            .contains(&"get_secret_variables".to_string()));
+
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec![signer.to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let viewer_queries: AllowedQueriesResponse = from_binary(&res).unwrap();
+        assert!(viewer_queries
+            .queries
+            .contains(&"get_secret_variables".to_string()));
+        assert!(!viewer_queries.queries.contains(&"list_secrets".to_string()));
+        assert!(viewer_queries.queries.len() < owner_queries.queries.len());
+    }
+
+    #[test]
+    fn bulk_check_access() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer1", "viewer2"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        // viewer2 keeps its key after being removed, viewer3 never generates one
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer3".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let accounts = vec![
+            "viewer1".to_string(),
+            "viewer2".to_string(),
+            "viewer3".to_string(),
+        ];
+        let query_msg = QueryMsg::BulkCheckAccess {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+            accounts: accounts.clone(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Owner only");
+
+        let query_msg = QueryMsg::BulkCheckAccess {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+            accounts,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let value: BulkCheckAccessResponse = from_binary(&res).unwrap();
+        let denied = |reason| AccessValidityResponse {
+            valid: false,
+            reason: Some(reason),
+        };
+        assert_eq!(
+            value.accounts,
+            vec![
+                (
+                    "viewer1".to_string(),
+                    AccessValidityResponse {
+                        valid: true,
+                        reason: None,
+                    }
+                ),
+                (
+                    "viewer2".to_string(),
+                    denied(AccessDeniedReason::NotAViewer)
+                ),
+                (
+                    "viewer3".to_string(),
+                    denied(AccessDeniedReason::InvalidViewingKey)
+                ),
+            ]
+        );
+
+        // an off-chain secret reference is read with GetSecretReference instead
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretReference {
+            uri: "ipfs://secrets".to_string(),
+            hash: Binary::from(vec![0; 32]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: BulkCheckAccessResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.accounts[0],
+            (
+                "viewer1".to_string(),
+                denied(AccessDeniedReason::Unreadable {
+                    detail:
+                        "Secret variables are stored off-chain, query GetSecretReference instead"
+                            .to_string()
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn immutable_instantiate() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            immutable: Some(ImmutableContents {
+                viewers: vec!["viewer1".to_string()],
+                secret_variables: secrets(&[("SECRET", "1")]),
+            }),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let messages = vec![
+            ExecuteMsg::SetViewers {
+                viewers: vec!["viewer2".to_string()],
+                skip_invalid: None,
+            },
+            ExecuteMsg::SetSecretVariables {
+                secret_variables: secrets(&[("SECRET", "2")]),
+                trim: None,
+            },
+            ExecuteMsg::TransferOwnership {
+                new_owner: "successor".to_string(),
+            },
+            ExecuteMsg::Pause { reason: None },
+            ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            },
+        ];
+        for exec_msg in messages {
+            let info = mock_info("creator", &[]);
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+            assert_eq!(
+                res.unwrap_err(),
+                StdError::generic_err(
+                    "The contract is immutable, its owner can no longer execute messages"
+                )
+            );
+        }
+
+        // the seeded viewer still reads the seeded secrets
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+        let query_msg = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: key,
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("SECRET", "1")]));
+    }
+
+    #[test]
+    fn secrets_are_returned_sorted_by_key() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        for key in ["zeta", "alpha", "mid"] {
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::SetSecretVariable {
+                key: key.to_string(),
+                value: key.to_uppercase(),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer1"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: keys[1].clone(),
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        assert_eq!(
+            String::from_utf8(res.to_vec()).unwrap(),
+            r#"{"alpha":"ALPHA","mid":"MID","zeta":"ZETA"}"#
+        );
+
+        let query_msg = QueryMsg::ListSecrets {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+            start_after: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: ListSecretsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value.secrets,
+            vec![
+                ("alpha".to_string(), "ALPHA".to_string()),
+                ("mid".to_string(), "MID".to_string()),
+                ("zeta".to_string(), "ZETA".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn auth_modes() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let enabled_modes = |deps: Deps| {
+            let res = query(deps, mock_env(), QueryMsg::GetAuthModes {}).unwrap();
+            let value: AuthModesResponse = from_binary(&res).unwrap();
+            value
+                .modes
+                .into_iter()
+                .filter(|m| m.enabled)
+                .map(|m| (m.mode, m.requires_transaction))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            enabled_modes(deps.as_ref()),
+            vec![
+                ("permit".to_string(), false),
+                ("viewing_key".to_string(), false),
+                ("audited".to_string(), true),
+            ]
+        );
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetReadQuorum {
+            read_quorum: Some(ReadQuorum {
+                quorum: 2,
+                window_blocks: 10,
+            }),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        assert_eq!(
+            enabled_modes(deps.as_ref()),
+            vec![("cosign".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn onboard_viewer() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("api_token", "abc"), ("db_password", "hunter2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::OnboardViewer {
+            viewer: "viewer1".to_string(),
+            keys: vec!["api_token".to_string()],
+            entropy: Some("entropy".to_string()),
+        };
+        let info = mock_info("anyone", &[]);
+        execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect_err("Only the owner can onboard viewers");
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            state.allowed_viewers,
+            vec![AllowedViewer {
+                address: deps.api.addr_canonicalize("viewer1").unwrap(),
+                keys: Some(vec!["api_token".to_string()]),
+            }]
+        );
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: key,
+            keys: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("api_token", "abc")]));
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::OnboardViewer {
+            viewer: "viewer2".to_string(),
+            keys: vec![],
+            entropy: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Onboarded viewers need at least one key to read")
+        );
+
+        // onboarding without entropy leaves the key to the viewer
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::OnboardViewer {
+            viewer: "viewer2".to_string(),
+            keys: vec!["db_password".to_string()],
+            entropy: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert!(res.data.is_none());
+        let viewer2 = deps.api.addr_canonicalize("viewer2").unwrap();
+        assert!(has_key_read(&deps.storage)
+            .may_load(viewer2.as_slice())
+            .unwrap()
+            .is_none());
+
+        // the key follows the contract's default TTL
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewingKeyTtl {
+            viewing_key_ttl_blocks: Some(100),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::OnboardViewer {
+            viewer: "viewer3".to_string(),
+            keys: vec!["api_token".to_string()],
+            entropy: Some("entropy".to_string()),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let viewer3 = deps.api.addr_canonicalize("viewer3").unwrap();
+        assert_eq!(
+            viewing_key_expiry_read(&deps.storage)
+                .may_load(viewer3.as_slice())
+                .unwrap(),
+            Some(mock_env().block.height + 100)
+        );
+    }
+
+    #[test]
+    fn compare_viewing_keys() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        // viewer1 generates a second key, which replaces its first
+        let mut keys = vec![];
+        for (account, entropy) in [
+            ("creator", "entropy"),
+            ("viewer1", "entropy"),
+            ("viewer1", "other entropy"),
+            ("viewer2", "entropy"),
+        ] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: entropy.to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let pair = |account: &str, viewing_key: &str| AccountKey {
+            account: account.to_string(),
+            viewing_key: viewing_key.to_string(),
+        };
+        let compare = |first: AccountKey, second: AccountKey| {
+            let query_msg = QueryMsg::CompareViewingKeys {
+                account: "creator".to_string(),
+                viewing_key: keys[0].clone(),
+                first,
+                second,
+            };
+            let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+            from_binary::<CompareViewingKeysResponse>(&res).unwrap()
+        };
+
+        assert_eq!(
+            compare(pair("viewer1", &keys[1]), pair("viewer1", &keys[2])),
+            CompareViewingKeysResponse {
+                first_valid: false,
+                second_valid: true,
+                same_account: true,
+            }
+        );
+        assert_eq!(
+            compare(pair("viewer1", &keys[2]), pair("viewer2", &keys[3])),
+            CompareViewingKeysResponse {
+                first_valid: true,
+                second_valid: true,
+                same_account: false,
+            }
+        );
+
+        let query_msg = QueryMsg::CompareViewingKeys {
+            account: "viewer1".to_string(),
+            viewing_key: keys[2].clone(),
+            first: pair("viewer1", &keys[2]),
+            second: pair("viewer2", &keys[3]),
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("Owner only");
+    }
+
+    #[test]
+    fn expired_key_falls_back_to_default() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("endpoint", "https://beta.example")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let readable_until = mock_env().block.height + 5;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetReadableUntil {
+            key: "endpoint".to_string(),
+            readable_until: None,
+            fallback: Some("https://example".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), info.clone(), exec_msg)
+            .expect_err("A fallback needs a height");
+        let exec_msg = ExecuteMsg::SetReadableUntil {
+            key: "endpoint".to_string(),
+            readable_until: Some(readable_until),
+            fallback: Some("https://example".to_string()),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let query_msg = QueryMsg::GetSecretVariables {
+            account: "viewer1".to_string(),
+            viewing_key: key,
+            keys: Some(vec!["endpoint".to_string()]),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("endpoint", "https://beta.example")]));
+
+        let mut env = mock_env();
+        env.block.height = readable_until;
+        let res = query(deps.as_ref(), env, query_msg).unwrap();
+        let ans: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(ans, secrets(&[("endpoint", "https://example")]));
+    }
+
+    #[test]
+    fn max_keys_total() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_keys_total: Some(2),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec![
+                "viewer1".to_string(),
+                "viewer2".to_string(),
+                "viewer3".to_string(),
+            ],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        for viewer in ["viewer1", "viewer2", "viewer1"].iter() {
+            let info = mock_info(viewer, &[]);
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+                .expect("Replacing a key does not count towards the limit");
+        }
+
+        let info = mock_info("viewer3", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone());
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => {
+                assert_eq!(msg, "The limit of 2 viewing keys has been reached")
+            }
+            _ => panic!("Must return error"),
+        }
+
+        let info = mock_info("creator", &[]);
+        let revoke_msg = ExecuteMsg::RevokeViewer {
+            viewer: "viewer1".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, revoke_msg).unwrap();
+
+        let info = mock_info("viewer3", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect("Revoking a key frees its slot");
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone())
+            .expect("The owner can always generate a key");
+
+        // the owner's key takes a slot, so the freed one is gone again
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RevokeViewer {
+            viewer: "viewer2".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer3".to_string(), "viewer4".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let info = mock_info("viewer4", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("The limit of 2 viewing keys has been reached")
+        );
+    }
 
     #[test]
-    fn proper_workflow() {
-        let mut deps = mock_dependencies_with_balance(&[Coin {
-            denom: "token".to_string(),
-            amount: Uint128::new(2),
-        }]);
+    fn policy_document() {
+        let mut deps = mock_dependencies();
         let info = mock_info("creator", &[]);
         let init_msg = InstantiateMsg {
-            prng_seed: b"prng_seed".to_vec().into(),
+            max_failed_auths: Some(3),
+            max_keys_total: Some(10),
+            ..default_init_msg()
         };
 
         let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
 
+        let validators = [Validator {
+            address: "validator".to_string(),
+            commission: Decimal::percent(1),
+            max_commission: Decimal::percent(2),
+            max_change_rate: Decimal::percent(1),
+        }];
+        deps.querier.update_staking("uscrt", &validators, &[]);
+
+        let height = mock_env().block.height;
+        let nft_gate = NftGate {
+            contract_addr: "nft".to_string(),
+            code_hash: "nft_hash".to_string(),
+            token_id: "1".to_string(),
+        };
+        let delegation_gate = DelegationGate {
+            validator: "validator".to_string(),
+            min_delegation: Uint128::new(1000),
+        };
+        let read_quorum = ReadQuorum {
+            quorum: 2,
+            window_blocks: 10,
+        };
+        let reveal_oracle = RevealOracle {
+            contract_addr: "oracle".to_string(),
+            code_hash: "oracle_hash".to_string(),
+            condition: "launched".to_string(),
+        };
+        let voucher_pubkey =
+            Binary::from_base64("AgHKtaco0KZsxd1f4xO5qDav79u/E7rt54iA2pM/AWNo").unwrap();
+        let setup = vec![
+            ExecuteMsg::SetViewers {
+                viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+                skip_invalid: None,
+            },
+            ExecuteMsg::SetViewerScope {
+                viewer: "viewer2".to_string(),
+                keys: Some(vec!["a".to_string()]),
+            },
+            ExecuteMsg::AddViewer {
+                viewer: "viewer3".to_string(),
+                viewing_key_ttl_blocks: Some(100),
+            },
+            ExecuteMsg::SetViewerMetadata {
+                viewer: "viewer1".to_string(),
+                metadata: Some("ci runner".to_string()),
+            },
+            ExecuteMsg::SetViewerMinEntropy {
+                viewer: "viewer1".to_string(),
+                min_entropy_length: Some(16),
+            },
+            ExecuteMsg::ScheduleViewerRemoval {
+                viewer: "viewer2".to_string(),
+                remove_at_height: Some(height + 50),
+            },
+            ExecuteMsg::SetSecretVariables {
+                secret_variables: secrets(&[("a", "1"), ("b", "2")]),
+                trim: None,
+            },
+            ExecuteMsg::SetReadOnce {
+                key: "a".to_string(),
+                read_once: true,
+            },
+            ExecuteMsg::SetReadableUntil {
+                key: "b".to_string(),
+                readable_until: Some(height + 1000),
+                fallback: None,
+            },
+            ExecuteMsg::SetNftGate {
+                nft_gate: Some(nft_gate.clone()),
+            },
+            ExecuteMsg::SetDelegationGate {
+                delegation_gate: Some(delegation_gate.clone()),
+            },
+            ExecuteMsg::SetVoucherPubkey {
+                pubkey: Some(voucher_pubkey.clone()),
+            },
+            ExecuteMsg::SetReadQuorum {
+                read_quorum: Some(read_quorum.clone()),
+            },
+            ExecuteMsg::SetRevealOracle {
+                reveal_oracle: Some(reveal_oracle.clone()),
+            },
+            ExecuteMsg::DisableActions {
+                actions: vec!["set_viewers".to_string()],
+            },
+            ExecuteMsg::TransferOwnership {
+                new_owner: "owner2".to_string(),
+            },
+        ];
+        for exec_msg in setup {
+            let info = mock_info("creator", &[]);
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let mut keys = vec![];
+        for account in ["creator", "viewer3"] {
+            let info = mock_info(account, &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy".to_string(),
+            };
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+            match ans {
+                ExecuteAnswer::ViewingKeyResponse { key } => keys.push(key),
+            }
+        }
+
+        let query_msg = QueryMsg::GetPolicyDocument {
+            account: "creator".to_string(),
+            viewing_key: keys[0].clone(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: PolicyDocumentResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            value,
+            PolicyDocumentResponse {
+                owner: "creator".to_string(),
+                pending_owner: Some("owner2".to_string()),
+                governance: None,
+                viewers: vec![
+                    PolicyViewer {
+                        address: "viewer1".to_string(),
+                        keys: None,
+                        viewing_key_expires_at: None,
+                        remove_at_height: None,
+                        viewing_key_ttl_blocks: None,
+                        min_entropy_length: Some(16),
+                        metadata: Some("ci runner".to_string()),
+                    },
+                    PolicyViewer {
+                        address: "viewer2".to_string(),
+                        keys: Some(vec!["a".to_string()]),
+                        viewing_key_expires_at: None,
+                        remove_at_height: Some(height + 50),
+                        viewing_key_ttl_blocks: None,
+                        min_entropy_length: None,
+                        metadata: None,
+                    },
+                    PolicyViewer {
+                        address: "viewer3".to_string(),
+                        keys: None,
+                        viewing_key_expires_at: Some(height + 100),
+                        remove_at_height: None,
+                        viewing_key_ttl_blocks: Some(100),
+                        min_entropy_length: None,
+                        metadata: None,
+                    },
+                ],
+                pending_viewers: vec![],
+                delegation_gate: Some(delegation_gate),
+                nft_gate: Some(nft_gate),
+                voucher_pubkey: Some(voucher_pubkey),
+                read_quorum: Some(read_quorum),
+                reveal_oracle: Some(reveal_oracle),
+                paused: false,
+                read_once_keys: vec!["a".to_string()],
+                readable_until: vec![("b".to_string(), height + 1000)].into_iter().collect(),
+                disabled_actions: vec!["set_viewers".to_string()],
+                immutable: false,
+                limits: PolicyLimits {
+                    min_update_interval_blocks: 0,
+                    max_failed_auths: 3,
+                    lockout_blocks: 0,
+                    max_total_bytes: None,
+                    auto_pause_threshold: 0,
+                    auto_pause_window_blocks: 0,
+                    viewing_key_length: VIEWING_KEY_LENGTH,
+                    viewing_key_ttl_blocks: None,
+                    max_history_len: 0,
+                    max_keys_total: Some(10),
+                },
+            }
+        );
+
+        let query_msg_as_viewer = QueryMsg::GetPolicyDocument {
+            account: "viewer3".to_string(),
+            viewing_key: keys[1].clone(),
+        };
+        query(deps.as_ref(), mock_env(), query_msg_as_viewer).expect_err("Owner only");
+    }
+
+    #[test]
+    fn validate_access_plan() {
+        let mut deps = mock_dependencies();
         let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_total_bytes: Some(256),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
 
+        let info = mock_info("creator", &[]);
         let exec_msg = ExecuteMsg::SetViewers {
             viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
         };
         let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
 
-        let exec_msg = ExecuteMsg::SetSecretVariables {
-            secret_variables: "this is a secret".to_string(),
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let planned = |viewer: &str, keys: Option<Vec<&str>>| PlannedViewer {
+            viewer: viewer.to_string(),
+            keys: keys.map(|keys| keys.into_iter().map(String::from).collect()),
+        };
+        let validate = |plan: Vec<PlannedViewer>| -> ValidateAccessPlanResponse {
+            let query_msg = QueryMsg::ValidateAccessPlan {
+                account: "creator".to_string(),
+                viewing_key: key.clone(),
+                plan,
+            };
+            from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+        };
+
+        let value = validate(vec![
+            planned("viewer1", None),
+            planned("viewer2", Some(vec!["a"])),
+        ]);
+        assert_eq!(
+            value,
+            ValidateAccessPlanResponse {
+                valid: true,
+                errors: vec![],
+            }
+        );
+
+        let long_key = "k".repeat(256);
+        let value = validate(vec![
+            planned("viewer1", None),
+            planned("x", None),
+            planned("viewer1", Some(vec!["a"])),
+            planned("viewer2", Some(vec![long_key.as_str()])),
+        ]);
+        assert!(!value.valid);
+        assert_eq!(
+            value.errors,
+            vec![
+                "Invalid viewer address: \"x\"".to_string(),
+                "Duplicate viewer: viewer1".to_string(),
+                "Storage budget of 256 bytes exceeded".to_string(),
+            ]
+        );
+
+        // nothing was applied
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.allowed_viewers.len(), 1);
+    }
+
+    #[test]
+    fn delegation_gated_access() {
+        use secret_toolkit::permit::{PermitParams, PermitSignature, PubKey};
+
+        // the signer and signature of the query_with_permit test
+        let signer = "secret1sdgykklx0hcp4xssavdn5plq83d5lm32w3uwdx";
+        let permit = Permit {
+                params: PermitParams {
+                    allowed_tokens: vec!["cosmos2contract".to_string()],
+                    permit_name: "secretvars".to_string(),
+                    chain_id: "secret-4".to_string(),
+                    permissions: vec![TokenPermissions::Owner],
+                },
+                signature: PermitSignature {
+                    pub_key: PubKey {
+                        r#type: "tendermint/PubKeySecp256k1".to_string(),
+                        value: Binary::from_base64("AgHKtaco0KZsxd1f4xO5qDav79u/E7rt54iA2pM/AWNo")
+                            .unwrap(),
+                    },
+                    signature: Binary::from_base64("HjgwjiA74IpPQF4t2GqStaZbscA4qy8zbfXOQppBZQMkrs3WQCKWwTxjtee6NbZjYbpDQgW8stk3tl+kdxhdyQ==").unwrap(),
+                },
+        };
+        let query_msg = QueryMsg::WithPermit {
+            permit: permit.clone(),
+            query: QueryWithPermit::GetSecretVariablesWithDelegation { keys: None },
+        };
+        let allowed_queries_msg = QueryMsg::WithPermit {
+            permit,
+            query: QueryWithPermit::GetAllowedQueries {},
+        };
+        let may_query_with_delegation = |deps: Deps| {
+            let res = query(deps, mock_env(), allowed_queries_msg.clone()).unwrap();
+            let value: AllowedQueriesResponse = from_binary(&res).unwrap();
+            value
+                .queries
+                .contains(&"get_secret_variables_with_delegation".to_string())
         };
 
+        let mut deps = mock_dependencies();
         let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
 
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("secret", "this is a secret")]),
+            trim: None,
+        };
         let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
 
+        query(deps.as_ref(), mock_env(), query_msg.clone())
+            .expect_err("Delegation gated access is not configured");
+
+        let validators = [Validator {
+            address: "validator".to_string(),
+            commission: Decimal::percent(1),
+            max_commission: Decimal::percent(2),
+            max_change_rate: Decimal::percent(1),
+        }];
+        let delegation = |amount: u128| FullDelegation {
+            delegator: Addr::unchecked(signer),
+            validator: "validator".to_string(),
+            amount: coin(amount, "uscrt"),
+            can_redelegate: coin(amount, "uscrt"),
+            accumulated_rewards: vec![],
+        };
+        deps.querier
+            .update_staking("uscrt", &validators, &[delegation(999)]);
+
+        let gate = |validator: &str, min_delegation: u128| ExecuteMsg::SetDelegationGate {
+            delegation_gate: Some(DelegationGate {
+                validator: validator.to_string(),
+                min_delegation: Uint128::new(min_delegation),
+            }),
+        };
+        let info = mock_info("creator", &[]);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            gate("validator", 0),
+        )
+        .expect_err("A zero minimum would let anyone read");
+        execute(deps.as_mut(), mock_env(), info, gate("unknown", 1000))
+            .expect_err("The validator has to exist");
         let info = mock_info("viewer1", &[]);
+        execute(deps.as_mut(), mock_env(), info, gate("validator", 1000))
+            .expect_err("Only the owner can set the delegation gate");
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, gate("validator", 1000)).unwrap();
+
+        assert!(!may_query_with_delegation(deps.as_ref()));
+        let res = query(deps.as_ref(), mock_env(), query_msg.clone());
+        match res {
+            Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                msg,
+                "A delegation of at least 1000 to validator is required to query secret variables"
+            ),
+            _ => panic!("Must return error"),
+        }
+
+        deps.querier
+            .update_staking("uscrt", &validators, &[delegation(1000)]);
+        assert!(may_query_with_delegation(deps.as_ref()));
+        let res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let value: BTreeMap<String, String> = from_binary(&res).unwrap();
+        assert_eq!(value, secrets(&[("secret", "this is a secret")]));
+    }
+
+    #[test]
+    fn explain_authorization() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("a", "1"), ("b", "2")]),
+            trim: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerScope {
+            viewer: "viewer1".to_string(),
+            keys: Some(vec!["a".to_string()]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
 
+        let info = mock_info("viewer1", &[]);
         let exec_msg = ExecuteMsg::GenerateViewingKey {
             entropy: "entropy".to_string(),
         };
         let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
-
         let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        let explain = |deps: Deps, viewing_key: &str, key: &str| -> ExplainAuthorizationResponse {
+            let query_msg = QueryMsg::ExplainAuthorization {
+                account: "viewer1".to_string(),
+                viewing_key: viewing_key.to_string(),
+                key: key.to_string(),
+            };
+            from_binary(&query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+
+        let value = explain(deps.as_ref(), &key, "a");
+        assert!(value.authorized);
+
+        let value = explain(deps.as_ref(), &key, "b");
+        assert!(!value.authorized);
+        let failing: Vec<_> = value.checks.iter().filter(|c| !c.passed).collect();
+        assert_eq!(
+            failing,
+            vec![&AuthorizationCheck {
+                check: "scope".to_string(),
+                passed: false,
+                detail: Some("b is outside the viewer's scope".to_string()),
+            }]
+        );
 
+        // a wrong viewing key reveals nothing about membership or scopes
+        let query_msg = QueryMsg::ExplainAuthorization {
+            account: "viewer1".to_string(),
+            viewing_key: "x".repeat(key.len()),
+            key: "b".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), query_msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Wrong viewing key for viewer1, or the account is locked out")
+        );
+
+        // tamper with the stored value without going through SetSecretVariables
+        config(&mut deps.storage)
+            .update(|mut state| -> StdResult<_> {
+                state.secret_variables = secrets(&[("a", "tampered"), ("b", "2")]);
+                Ok(state)
+            })
+            .unwrap();
+        let value = explain(deps.as_ref(), &key, "a");
+        assert!(!value.authorized);
+        let failing: Vec<_> = value.checks.iter().filter(|c| !c.passed).collect();
+        assert_eq!(
+            failing,
+            vec![&AuthorizationCheck {
+                check: "read".to_string(),
+                passed: false,
+                detail: Some(
+                    "Secret variables do not match their checksum, storage may be corrupted"
+                        .to_string()
+                ),
+            }]
+        );
+    }
+
+    #[test]
+    fn self_reported_failed_auths_do_not_pause() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            auto_pause_threshold: Some(2),
+            auto_pause_window_blocks: Some(10),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
         let key = match ans {
             ExecuteAnswer::ViewingKeyResponse { key } => key,
         };
 
-        let exec_msg = QueryMsg::GetSecretVariables {
+        let report_msg = ExecuteMsg::ReportFailedAuth {
+            account: "stranger".to_string(),
+            viewing_key: "wrong".to_string(),
+        };
+        for _ in 0..3 {
+            let info = mock_info("stranger", &[]);
+            let res = execute(deps.as_mut(), mock_env(), info, report_msg.clone()).unwrap();
+            assert!(!res.attributes.iter().any(|a| a.key == "paused"));
+        }
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert!(!state.paused);
+        assert!(state.recent_failed_auths.is_empty());
+
+        let query_msg = QueryMsg::GetSecretVariables {
             viewing_key: key,
             account: "viewer1".to_string(),
+            keys: None,
         };
+        let _res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    }
 
-        let res = query(deps.as_ref(), mock_env(), exec_msg).unwrap();
+    #[test]
+    fn viewing_key_length_has_to_match_generated_keys() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            viewing_key_length: Some(32),
+            ..default_init_msg()
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("viewing_key_length has to be 52, the length of generated keys")
+        );
 
-        let ans: String = from_binary(&res).unwrap();
+        let init_msg = InstantiateMsg {
+            config_json: Some(r#"{"viewing_key_length":64}"#.to_string()),
+            ..default_init_msg()
+        };
+        instantiate(deps.as_mut(), mock_env(), info.clone(), init_msg)
+            .expect_err("config_json is checked the same way");
 
-        assert_eq!(ans, "this is a secret".to_string());
+        let init_msg = InstantiateMsg {
+            viewing_key_length: Some(VIEWING_KEY_LENGTH),
+            ..default_init_msg()
+        };
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
 
-        let exec_msg = QueryMsg::GetSecretVariables {
-            viewing_key: "asda".to_string(),
-            account: "viewer1".to_string(),
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
         };
 
-        let _res = query(deps.as_ref(), mock_env(), exec_msg)
-            .expect_err("Hacker cannot query secret variables");
+        let query_msg = QueryMsg::GetViewers {
+            account: "creator".to_string(),
+            viewing_key: key,
+        };
+        let _res = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+    }
+
+    #[test]
+    fn governance_cannot_be_bypassed() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, default_init_msg()).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer1".to_string(), "viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetGovernance {
+            governance: Some("governance".to_string()),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let rejected = [
+            ExecuteMsg::SetViewers {
+                viewers: vec!["viewer1".to_string(), "viewer3".to_string()],
+                skip_invalid: None,
+            },
+            ExecuteMsg::ImportViewersCsv {
+                csv: "viewer1,viewer3".to_string(),
+                skip_invalid: None,
+            },
+            ExecuteMsg::SetViewerScope {
+                viewer: "viewer3".to_string(),
+                keys: Some(vec!["a".to_string()]),
+            },
+        ];
+        for exec_msg in rejected.iter() {
+            let info = mock_info("creator", &[]);
+            let res = execute(deps.as_mut(), mock_env(), info, exec_msg.clone());
+            match res {
+                Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+                    msg,
+                    "Viewers wait for governance approval, use AddViewer instead"
+                ),
+                _ => panic!("{:?} must not add a viewer", exec_msg),
+            }
+        }
+
+        // scoping and removing existing viewers needs no approval
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerScope {
+            viewer: "viewer2".to_string(),
+            keys: Some(vec!["a".to_string()]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec!["viewer2".to_string()],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.allowed_viewers.len(), 1);
+        assert!(state.pending_viewers.is_empty());
+    }
+
+    #[test]
+    fn storage_budget_counts_history() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_total_bytes: Some(30),
+            max_history_len: Some(5),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        // 13 bytes each, the third update only fits once the first two versions are dropped
+        let mut env = mock_env();
+        for value in ["0123456789", "abcdefghij", "ABCDEFGHIJ"].iter() {
+            env.block.height += 1;
+            let info = mock_info("creator", &[]);
+            let exec_msg = ExecuteMsg::SetSecretVariables {
+                secret_variables: secrets(&[("key", value)]),
+                trim: None,
+            };
+            let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+        }
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            state.secret_variables_history,
+            vec![(env.block.height, secrets(&[("key", "abcdefghij")]))]
+        );
+
+        // rolling back on a full budget works the same way
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RollbackSecretVariables { steps: 1 };
+        let _res = execute(deps.as_mut(), env.clone(), info, exec_msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(state.secret_variables, secrets(&[("key", "abcdefghij")]));
+        assert_eq!(
+            state.secret_variables_history,
+            vec![(env.block.height, secrets(&[("key", "ABCDEFGHIJ")]))]
+        );
+
+        // live data that does not fit on its own is still rejected
+        env.block.height += 1;
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetSecretVariables {
+            secret_variables: secrets(&[("key", "0123456789abcdefghijABCDEFGHIJ")]),
+            trim: None,
+        };
+        let err = execute(deps.as_mut(), env, info, exec_msg).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Storage budget of 30 bytes exceeded")
+        );
+    }
+
+    #[test]
+    fn removed_viewer_settings_are_cleared() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = default_init_msg();
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let viewer1 = deps.api.addr_canonicalize("viewer1").unwrap();
+        let setup = |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            for exec_msg in [
+                ExecuteMsg::AddViewer {
+                    viewer: "viewer1".to_string(),
+                    viewing_key_ttl_blocks: Some(100),
+                },
+                ExecuteMsg::SetViewerMinEntropy {
+                    viewer: "viewer1".to_string(),
+                    min_entropy_length: Some(32),
+                },
+                ExecuteMsg::SetViewerMetadata {
+                    viewer: "viewer1".to_string(),
+                    metadata: Some("ci runner".to_string()),
+                },
+            ] {
+                let info = mock_info("creator", &[]);
+                let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+            }
+            let info = mock_info("viewer1", &[]);
+            let exec_msg = ExecuteMsg::GenerateViewingKey {
+                entropy: "entropy that is at least 32 bytes".to_string(),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        };
+        let assert_cleared = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            let storage = &deps.storage;
+            assert_eq!(
+                viewer_key_ttl_read(storage)
+                    .may_load(viewer1.as_slice())
+                    .unwrap(),
+                None
+            );
+            assert_eq!(
+                viewer_min_entropy_read(storage)
+                    .may_load(viewer1.as_slice())
+                    .unwrap(),
+                None
+            );
+            assert_eq!(
+                viewer_metadata_read(storage)
+                    .may_load(viewer1.as_slice())
+                    .unwrap(),
+                None
+            );
+            assert_eq!(
+                viewing_key_expiry_read(storage)
+                    .may_load(viewer1.as_slice())
+                    .unwrap(),
+                None
+            );
+        };
+
+        setup(&mut deps);
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::RevokeViewer {
+            viewer: "viewer1".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_cleared(&deps);
+
+        setup(&mut deps);
+        let info = mock_info("viewer1", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::LeaveAsViewer {},
+        )
+        .unwrap();
+        assert_cleared(&deps);
+
+        // added back, the viewer gets the defaults rather than its old overrides
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::AddViewer {
+            viewer: "viewer1".to_string(),
+            viewing_key_ttl_blocks: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "short".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_cleared(&deps);
+
+        // SetViewers drops the overrides too
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewerMinEntropy {
+            viewer: "viewer1".to_string(),
+            min_entropy_length: Some(32),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::SetViewers {
+            viewers: vec![],
+            skip_invalid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        assert_cleared(&deps);
+    }
+
+    #[test]
+    fn accepting_ownership_invalidates_previous_owner_key() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let init_msg = InstantiateMsg {
+            max_keys_total: Some(1),
+            ..default_init_msg()
+        };
+
+        let _res = instantiate(deps.as_mut(), mock_env(), info, init_msg).unwrap();
+
+        let info = mock_info("creator", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        let ans: ExecuteAnswer = from_binary(&res.data.unwrap_or_default()).unwrap();
+        let creator_key = match ans {
+            ExecuteAnswer::ViewingKeyResponse { key } => key,
+        };
+
+        for (account, exec_msg) in [
+            (
+                "creator",
+                ExecuteMsg::TransferOwnership {
+                    new_owner: "successor".to_string(),
+                },
+            ),
+            ("successor", ExecuteMsg::AcceptOwnership {}),
+            (
+                "successor",
+                ExecuteMsg::SetViewers {
+                    viewers: vec!["viewer1".to_string()],
+                    skip_invalid: None,
+                },
+            ),
+        ] {
+            let info = mock_info(account, &[]);
+            let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
+        }
+
+        let creator = deps.api.addr_canonicalize("creator").unwrap();
+        assert!(has_key_read(&deps.storage)
+            .may_load(creator.as_slice())
+            .unwrap()
+            .is_none());
+        let query_msg = QueryMsg::GetViewers {
+            account: "creator".to_string(),
+            viewing_key: creator_key,
+        };
+        query(deps.as_ref(), mock_env(), query_msg).expect_err("The previous owner is out");
+
+        // the freed slot is available to the viewer
+        let info = mock_info("viewer1", &[]);
+        let exec_msg = ExecuteMsg::GenerateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, exec_msg).unwrap();
     }
 }
@@ -1,3 +1,6 @@
+#[cfg(all(feature = "deterministic-keys", not(debug_assertions)))]
+compile_error!("the deterministic-keys feature must not be used in release builds");
+
 pub mod contract;
 pub mod msg;
 pub mod state;
@@ -1,36 +1,771 @@
-use cosmwasm_std::Binary;
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Binary, Timestamp};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use secret_toolkit::permit::Permit;
+
+use crate::state::{
+    DelegationGate, MembershipAction, NftGate, ReadQuorum, RevealOracle, SecretReference,
+};
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub prng_seed: Binary,
+    /// Minimum number of blocks between two secret variable updates
+    pub min_update_interval_blocks: Option<u64>,
+    /// Number of reported failed authentications after which an account is locked out
+    pub max_failed_auths: Option<u32>,
+    /// Number of blocks a locked out account has to wait before it can query again
+    pub lockout_blocks: Option<u64>,
+    /// Upper bound on the bytes used by secret variables and viewers together. The
+    /// oldest earlier versions of the secret variables are dropped to make room first
+    pub max_total_bytes: Option<u64>,
+    /// Number of failed authentications, for any account, that pauses all secret reads
+    pub auto_pause_threshold: Option<u32>,
+    /// Number of blocks over which failed authentications count towards the auto-pause
+    pub auto_pause_window_blocks: Option<u64>,
+    /// Length every supplied viewing key must have. Generated keys are always 52
+    /// characters long, so any other length is rejected
+    pub viewing_key_length: Option<u32>,
+    /// Number of earlier secret variable versions kept for rollback, none by default
+    pub max_history_len: Option<u32>,
+    /// Number of viewing keys that can exist at once, revoking a key frees its slot.
+    /// The owner's key takes a slot too, but the owner can always generate one
+    pub max_keys_total: Option<u32>,
+    /// JSON encoded `InstantiateConfig`, for deployments that keep their limits in
+    /// version control. A limit can be set here or in the field above, not both.
+    pub config_json: Option<String>,
+    /// Seeds the contract with these contents and renounces ownership right away,
+    /// leaving it read-only for good
+    pub immutable: Option<ImmutableContents>,
+}
+
+/// What an immutable contract is born with, nothing can be changed afterwards
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ImmutableContents {
+    pub viewers: Vec<String>,
+    pub secret_variables: BTreeMap<String, String>,
+}
+
+/// The limits of `InstantiateMsg`, as accepted in `config_json`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct InstantiateConfig {
+    pub min_update_interval_blocks: Option<u64>,
+    pub max_failed_auths: Option<u32>,
+    pub lockout_blocks: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+    pub auto_pause_threshold: Option<u32>,
+    pub auto_pause_window_blocks: Option<u64>,
+    pub viewing_key_length: Option<u32>,
+    pub max_history_len: Option<u32>,
+    pub max_keys_total: Option<u32>,
+}
+
+/// Owner signed permission for `viewer` to add itself as a viewer before `expires_at_height`
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct Voucher {
+    pub viewer: String,
+    pub expires_at_height: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    SetViewers { viewers: Vec<String> },
-    SetSecretVariables { secret_variables: String },
-    GenerateViewingKey { entropy: String },
+    SetViewers {
+        viewers: Vec<String>,
+        /// skip addresses that fail to canonicalize instead of rejecting the batch
+        skip_invalid: Option<bool>,
+    },
+    SetSecretVariables {
+        secret_variables: BTreeMap<String, String>,
+        /// strip surrounding whitespace from every value before storing it
+        trim: Option<bool>,
+    },
+    InitSecretVariables {
+        secret_variables: BTreeMap<String, String>,
+    },
+    SetSecretVariable {
+        key: String,
+        value: String,
+    },
+    RemoveSecretVariable {
+        key: String,
+    },
+    SetReadOnce {
+        key: String,
+        read_once: bool,
+    },
+    /// `None` keeps `key` readable for good
+    SetReadableUntil {
+        key: String,
+        readable_until: Option<u64>,
+        /// Returned to viewers in place of the value once it is no longer readable
+        fallback: Option<String>,
+    },
+    AcknowledgeRead {
+        key: String,
+    },
+    RollbackSecretVariables {
+        steps: u32,
+    },
+    SetVoucherPubkey {
+        pubkey: Option<Binary>,
+    },
+    DisableActions {
+        actions: Vec<String>,
+    },
+    SelfTest {},
+    RedeemVoucher {
+        voucher: Voucher,
+        signature: Binary,
+    },
+    GenerateViewingKey {
+        entropy: String,
+    },
+    SetPublicVariables {
+        public_variables: BTreeMap<String, String>,
+    },
+    ReportFailedAuth {
+        account: String,
+        viewing_key: String,
+    },
+    ImportViewersCsv {
+        csv: String,
+        /// skip addresses that fail to canonicalize instead of rejecting the import
+        skip_invalid: Option<bool>,
+    },
+    SetNftGate {
+        nft_gate: Option<NftGate>,
+    },
+    SetDelegationGate {
+        delegation_gate: Option<DelegationGate>,
+    },
+    SetRevealOracle {
+        reveal_oracle: Option<RevealOracle>,
+    },
+    SetRecoveryHintHash {
+        recovery_hint_hash: Option<Binary>,
+    },
+    SetSecretReference {
+        uri: String,
+        hash: Binary,
+    },
+    Pause {
+        reason: Option<String>,
+    },
+    Unpause {},
+    SetGovernance {
+        governance: Option<String>,
+    },
+    AddViewer {
+        viewer: String,
+        /// Overrides the contract-wide viewing key TTL for this viewer
+        viewing_key_ttl_blocks: Option<u64>,
+    },
+    /// Adds `viewer` scoped to `keys`, at least one, and generates its viewing key when `entropy` is given
+    OnboardViewer {
+        viewer: String,
+        keys: Vec<String>,
+        entropy: Option<String>,
+    },
+    LeaveAsViewer {},
+    SetReadQuorum {
+        read_quorum: Option<ReadQuorum>,
+    },
+    SetViewingKeyTtl {
+        viewing_key_ttl_blocks: Option<u64>,
+    },
+    CosignRead {
+        viewing_key: String,
+    },
+    GetSecretVariablesAudited {
+        viewing_key: String,
+        keys: Option<Vec<String>>,
+    },
+    SetViewerMetadata {
+        viewer: String,
+        metadata: Option<String>,
+    },
+    /// `None` lets `viewer` generate viewing keys from any entropy again
+    SetViewerMinEntropy {
+        viewer: String,
+        min_entropy_length: Option<u32>,
+    },
+    SetViewerScope {
+        viewer: String,
+        keys: Option<Vec<String>>,
+    },
+    StageSecrets {
+        secret_variables: BTreeMap<String, String>,
+    },
+    CommitStaged {},
+    DiscardStaged {},
+    SetManifestHash {
+        manifest_hash: Option<Binary>,
+    },
+    RevokeViewingKeysFor {
+        viewers: Vec<String>,
+    },
+    RevokeViewer {
+        viewer: String,
+    },
+    /// `None` cancels a removal scheduled earlier
+    ScheduleViewerRemoval {
+        viewer: String,
+        remove_at_height: Option<u64>,
+    },
+    TransferOwnership {
+        new_owner: String,
+    },
+    AcceptOwnership {},
+    ApproveViewer {
+        viewer: String,
+    },
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     // GetCount returns the current count as a json-encoded number
     GetSecretVariables {
         account: String,
         viewing_key: String,
+        keys: Option<Vec<String>>,
+    },
+    // CanonicalizeAddress returns the hex-encoded canonical form of an address
+    CanonicalizeAddress {
+        address: String,
+    },
+    // GetPublicVariables returns the non-secret variables, no viewing key required
+    GetPublicVariables {},
+    // GetSchemaVersion returns the version of the storage layout
+    GetSchemaVersion {},
+    // HealthCheck tells whether secret reads are paused and why, no viewing key required
+    HealthCheck {},
+    // GetStats returns monitoring data about the contract, no viewing key required
+    GetStats {},
+    // GetBlockInfo returns the block height and time the contract sees, no viewing key
+    // required, so clients can compute expiries against the chain's clock
+    GetBlockInfo {},
+    // GetAuthModes returns the ways secret variables can currently be read and what each
+    // costs the client, no viewing key required
+    GetAuthModes {},
+    // GetOwnerActivity returns how long ago the owner last sent a transaction
+    GetOwnerActivity {},
+    // GetSecretVariablesWithNft lets the owner of the gating NFT read the secret variables,
+    // authenticating with their viewing key on the NFT contract
+    GetSecretVariablesWithNft {
+        account: String,
+        nft_viewing_key: String,
+    },
+    // GetSecretReference returns where off-chain secret variables are stored, if they are
+    GetSecretReference {
+        account: String,
+        viewing_key: String,
+    },
+    // EstimateSetGas returns a rough, approximate gas cost of storing `value` as the
+    // secret variable `key`
+    EstimateSetGas {
+        key: String,
+        value: String,
+    },
+    // GetMembershipHistory returns the log of viewer additions and removals, owner only
+    GetMembershipHistory {
+        account: String,
+        viewing_key: String,
+        start: Option<u64>,
+        limit: Option<u32>,
+    },
+    // OwnerDashboard summarizes viewers, secrets and pending changes in one response, owner only
+    OwnerDashboard {
+        account: String,
+        viewing_key: String,
+    },
+    // ListSecrets returns the secret variables ordered by key, a page at a time, owner only
+    ListSecrets {
+        account: String,
+        viewing_key: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    // FindViewingKeyOwner returns the viewer whose viewing key is `candidate_key`, owner only
+    FindViewingKeyOwner {
+        account: String,
+        viewing_key: String,
+        candidate_key: String,
+    },
+    // GetRecentActions returns the latest executes with their senders, newest first, owner only
+    GetRecentActions {
+        account: String,
+        viewing_key: String,
+    },
+    // GetStateFingerprint returns a hash over the configuration, but not the secrets, owner only
+    GetStateFingerprint {
+        account: String,
+        viewing_key: String,
+    },
+    // GetViewers returns every allowed viewer, owner only
+    GetViewers {
+        account: String,
+        viewing_key: String,
+    },
+    // ListViewersWithoutKeys returns the viewers that have not generated a viewing key, owner only
+    ListViewersWithoutKeys {
+        account: String,
+        viewing_key: String,
+    },
+    // GetManifestHash returns the hash of the owner's secret distribution manifest
+    GetManifestHash {
+        account: String,
+        viewing_key: String,
+    },
+    // GetViewerMetadata returns the hint the owner stored for a viewer, owner only
+    GetViewerMetadata {
+        account: String,
+        viewing_key: String,
+        viewer: String,
+    },
+    // IsAccessValid tells a viewer whether its viewing key currently grants access, and why not.
+    // A wrong viewing key, or an account that is locked out, gets an error instead of an answer
+    IsAccessValid {
+        account: String,
+        viewing_key: String,
+    },
+    // ExplainAuthorization tells a viewer which of the checks for reading the secret variable
+    // `key` pass and why the others fail. Like IsAccessValid, it needs the viewer's key
+    ExplainAuthorization {
+        account: String,
+        viewing_key: String,
+        key: String,
+    },
+    // BulkCheckAccess tells the owner, for each of `accounts`, whether it could read the
+    // secret variables now with the viewing key it generated, and why not
+    BulkCheckAccess {
+        account: String,
+        viewing_key: String,
+        accounts: Vec<String>,
+    },
+    // CompareViewingKeys tells the owner whether `first` and `second` each authenticate an
+    // allowed viewer, and whether they are the same account
+    CompareViewingKeys {
+        account: String,
+        viewing_key: String,
+        first: AccountKey,
+        second: AccountKey,
+    },
+    // ValidateAccessPlan reports what would go wrong replacing the viewers and their scopes
+    // with `plan`, without changing anything, owner only
+    ValidateAccessPlan {
+        account: String,
+        viewing_key: String,
+        plan: Vec<PlannedViewer>,
+    },
+    // GetAccessCounts returns how many audited reads each viewer made, owner only
+    GetAccessCounts {
+        account: String,
+        viewing_key: String,
+    },
+    // GetPendingRemovals returns the configured viewers with a scheduled removal and the
+    // height it takes effect at, owner only
+    GetPendingRemovals {
+        account: String,
+        viewing_key: String,
+    },
+    // GetPolicyDocument returns who can do what, and under which limits, in one document for
+    // external review, owner only
+    GetPolicyDocument {
+        account: String,
+        viewing_key: String,
+    },
+    // VerifySecretMatches tells a viewer whether the sha256 of the secret variable `key`
+    // equals `candidate_hash`, without returning the value
+    VerifySecretMatches {
+        account: String,
+        viewing_key: String,
+        key: String,
+        candidate_hash: Binary,
+    },
+    // GetSecretVariablesHistory returns the earlier secret variables with the height they
+    // were replaced at, oldest first, owner only
+    GetSecretVariablesHistory {
+        account: String,
+        viewing_key: String,
+    },
+    // WithPermit runs `query` as the signer of `permit`, instead of authenticating with a viewing key.
+    // A permit named `<name>@<height>` stops working at that block height
+    WithPermit {
+        permit: Permit,
+        query: QueryWithPermit,
+    },
+    // GetRecoveryHintHash returns the owner's recovery hint hash, owner only
+    GetRecoveryHintHash {
+        account: String,
+        viewing_key: String,
     },
 }
 
+/// Queries that can be authenticated with a permit carrying `TokenPermissions::Owner`
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    GetSecretVariables {
+        keys: Option<Vec<String>>,
+    },
+    /// Secret variables for a signer delegating enough to the gating validator,
+    /// whether or not it is a viewer
+    GetSecretVariablesWithDelegation {
+        keys: Option<Vec<String>>,
+    },
+    /// The `QueryMsg` names the signer is authorized to use, answered for any signer.
+    /// Includes `get_secret_variables_with_delegation` while the signer delegates enough
+    GetAllowedQueries {},
+}
+
 // We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AllowedQueriesResponse {
+    pub queries: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
 pub struct CountResponse {
     pub count: i32,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct CanonicalAddressResponse {
+    pub canonical: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub ok: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SelfTestResponse {
+    /// Whether every check passed
+    pub ok: bool,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct BlockInfoResponse {
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AuthModeInfo {
+    /// `permit`, `viewing_key`, `nft`, `audited` or `cosign`
+    pub mode: String,
+    pub enabled: bool,
+    /// Whether every read has to be sent as a transaction and pay gas
+    pub requires_transaction: bool,
+    pub cost_hint: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AuthModesResponse {
+    /// Cheapest mode first
+    pub modes: Vec<AuthModeInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SchemaVersionResponse {
+    pub schema_version: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessDeniedReason {
+    Paused,
+    LockedOut,
+    InvalidViewingKey,
+    ExpiredViewingKey,
+    NotAViewer,
+    /// The viewer would be let in, but reading fails, as the read itself reports it
+    Unreadable {
+        detail: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AccessValidityResponse {
+    pub valid: bool,
+    pub reason: Option<AccessDeniedReason>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AuthorizationCheck {
+    pub check: String,
+    pub passed: bool,
+    /// Why the check failed, as the read itself would report it
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ExplainAuthorizationResponse {
+    /// Whether every check passed
+    pub authorized: bool,
+    pub checks: Vec<AuthorizationCheck>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct BulkCheckAccessResponse {
+    pub accounts: Vec<(String, AccessValidityResponse)>,
+}
+
+/// An account and a viewing key claimed to be its own
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AccountKey {
+    pub account: String,
+    pub viewing_key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct CompareViewingKeysResponse {
+    pub first_valid: bool,
+    pub second_valid: bool,
+    /// Compared by canonical address, whether or not the keys are valid
+    pub same_account: bool,
+}
+
+/// A viewer as proposed in an access plan, `None` keys means every key
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct PlannedViewer {
+    pub viewer: String,
+    pub keys: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ValidateAccessPlanResponse {
+    /// Whether the plan could be applied as is
+    pub valid: bool,
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SecretVariablesHistoryResponse {
+    pub history: Vec<(u64, BTreeMap<String, String>)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SecretMatchesResponse {
+    pub matches: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct HealthCheckResponse {
+    pub paused: bool,
+    pub pause_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct OwnerActivityResponse {
+    pub last_owner_activity_height: u64,
+    pub blocks_since_owner_activity: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct StatsResponse {
+    /// Height at which a viewer was last added, by any means
+    pub last_grant_height: Option<u64>,
+    /// Read once secrets acknowledged by all viewers together
+    pub acknowledged_reads: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct RecoveryHintHashResponse {
+    pub recovery_hint_hash: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct RecentActionInfo {
+    pub action: String,
+    pub actor: String,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct RecentActionsResponse {
+    pub actions: Vec<RecentActionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct StateFingerprintResponse {
+    pub fingerprint: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ViewersResponse {
+    pub viewers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AccessCountsResponse {
+    pub access_counts: Vec<(String, u64)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct PendingRemovalsResponse {
+    pub pending_removals: Vec<(String, u64)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct PolicyDocumentResponse {
+    pub owner: String,
+    /// Address ownership was offered to, until it accepts
+    pub pending_owner: Option<String>,
+    /// Contract that approves viewer additions, the only role besides the owner
+    pub governance: Option<String>,
+    pub viewers: Vec<PolicyViewer>,
+    /// Viewers waiting for governance approval
+    pub pending_viewers: Vec<String>,
+    /// Delegators that may read the secret variables without being viewers
+    pub delegation_gate: Option<DelegationGate>,
+    /// Token whose owner may read the secret variables without being a viewer
+    pub nft_gate: Option<NftGate>,
+    /// Key whose signed vouchers let an address add itself as a viewer
+    pub voucher_pubkey: Option<Binary>,
+    pub read_quorum: Option<ReadQuorum>,
+    /// Oracle asked before secret variables are returned
+    pub reveal_oracle: Option<RevealOracle>,
+    pub paused: bool,
+    /// Secret keys deleted once a viewer acknowledges reading them
+    pub read_once_keys: Vec<String>,
+    /// Height from which each listed secret key is no longer readable by viewers
+    pub readable_until: BTreeMap<String, u64>,
+    pub disabled_actions: Vec<String>,
+    pub immutable: bool,
+    pub limits: PolicyLimits,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct PolicyViewer {
+    pub address: String,
+    /// Secret keys this viewer may read, `None` means every key
+    pub keys: Option<Vec<String>>,
+    /// Height at which the viewer's current viewing key stops working
+    pub viewing_key_expires_at: Option<u64>,
+    /// Height at which a scheduled removal takes effect
+    pub remove_at_height: Option<u64>,
+    /// The viewer's own viewing key TTL, used before the contract default
+    pub viewing_key_ttl_blocks: Option<u64>,
+    pub min_entropy_length: Option<u32>,
+    pub metadata: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct PolicyLimits {
+    pub min_update_interval_blocks: u64,
+    pub max_failed_auths: u32,
+    pub lockout_blocks: u64,
+    pub max_total_bytes: Option<u64>,
+    pub auto_pause_threshold: u32,
+    pub auto_pause_window_blocks: u64,
+    pub viewing_key_length: u32,
+    pub viewing_key_ttl_blocks: Option<u64>,
+    pub max_history_len: u32,
+    pub max_keys_total: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ManifestHashResponse {
+    pub manifest_hash: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ViewerMetadataResponse {
+    pub metadata: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SecretReferenceResponse {
+    pub secret_reference: Option<SecretReference>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct EstimateGasResponse {
+    pub gas: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct MembershipChangeInfo {
+    pub viewer: String,
+    pub action: MembershipAction,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct MembershipHistoryResponse {
+    pub changes: Vec<MembershipChangeInfo>,
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct OwnerDashboardResponse {
+    pub viewer_count: u32,
+    /// Viewers waiting for governance approval
+    pub pending_viewer_count: u32,
+    pub secret_count: u32,
+    pub last_updated_height: Option<u64>,
+    pub has_staged_secret_variables: bool,
+    /// Address ownership was offered to, until it accepts
+    pub pending_owner: Option<String>,
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ListSecretsResponse {
+    pub secrets: Vec<(String, String)>,
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ViewingKeyOwnerResponse {
+    pub viewer: Option<String>,
+}
+
+// The subset of the SNIP-721 query interface used to check NFT ownership
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Snip721QueryMsg {
+    OwnerOf {
+        token_id: String,
+        viewer: Option<Snip721ViewerInfo>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct Snip721ViewerInfo {
+    pub address: String,
+    pub viewing_key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Snip721QueryAnswer {
+    OwnerOf { owner: String },
+}
+
+// The query a reveal oracle has to answer
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQueryMsg {
+    IsConditionMet { condition: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQueryAnswer {
+    IsConditionMet { met: bool },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
 pub enum ExecuteAnswer {
     ViewingKeyResponse { key: String },
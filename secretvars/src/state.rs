@@ -1,16 +1,182 @@
+use std::collections::BTreeMap;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Storage};
-use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use cosmwasm_std::{Binary, CanonicalAddr, Storage, Uint128};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
 
 pub static CONFIG_KEY: &[u8] = b"config";
+pub static FAILED_AUTHS_KEY: &[u8] = b"failed_auths";
+pub static MEMBERSHIP_LOG_KEY: &[u8] = b"membership_log";
+pub static MEMBERSHIP_LOG_LEN_KEY: &[u8] = b"membership_log_len";
+pub static LAST_GRANT_HEIGHT_KEY: &[u8] = b"last_grant_height";
+pub static VIEWER_METADATA_KEY: &[u8] = b"viewer_metadata";
+pub static LAST_OWNER_ACTIVITY_KEY: &[u8] = b"last_owner_activity";
+pub static HAS_KEY_KEY: &[u8] = b"has_key";
+pub static RECENT_ACTIONS_KEY: &[u8] = b"recent_actions";
+pub static VIEWER_KEY_TTL_KEY: &[u8] = b"viewer_key_ttl";
+pub static VIEWING_KEY_EXPIRY_KEY: &[u8] = b"viewing_key_expiry";
+pub static REDEEMED_VOUCHERS_KEY: &[u8] = b"redeemed_vouchers";
+pub static ACKNOWLEDGED_READS_KEY: &[u8] = b"acknowledged_reads";
+pub static VIEWER_MIN_ENTROPY_KEY: &[u8] = b"viewer_min_entropy";
+pub static LIVE_VIEWING_KEYS_KEY: &[u8] = b"live_viewing_keys";
+pub const PREFIX_REVOKED_PERMITS: &str = "revoked_permits";
+
+/// Version of the `State` layout, bump whenever stored fields change meaning
+pub const SCHEMA_VERSION: u8 = 3;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
 pub struct State {
     pub owner: CanonicalAddr,
-    pub allowed_viewers: Vec<CanonicalAddr>,
-    pub secret_variables: String,
+    /// Address the owner offered ownership to, until it accepts
+    pub pending_owner: Option<CanonicalAddr>,
+    pub allowed_viewers: Vec<AllowedViewer>,
+    /// Named secrets, e.g. `db_password`, each set and removed on its own. Kept
+    /// sorted by key, which is the order every query returns them in
+    pub secret_variables: BTreeMap<String, String>,
+    pub public_variables: BTreeMap<String, String>,
+    pub min_update_interval_blocks: u64,
+    pub last_updated_height: Option<u64>,
+    pub schema_version: u8,
+    pub max_failed_auths: u32,
+    pub lockout_blocks: u64,
+    pub max_total_bytes: Option<u64>,
+    pub nft_gate: Option<NftGate>,
+    pub delegation_gate: Option<DelegationGate>,
+    /// Contract that has to report its condition as met before secrets are revealed
+    pub reveal_oracle: Option<RevealOracle>,
+    pub recovery_hint_hash: Option<Binary>,
+    pub secret_reference: Option<SecretReference>,
+    pub secret_variables_checksum: Binary,
+    pub paused: bool,
+    /// Why reads are paused, shown to viewers in `HealthCheck`
+    pub pause_reason: Option<String>,
+    pub auto_pause_threshold: u32,
+    pub auto_pause_window_blocks: u64,
+    /// Heights of the failed authentications still inside the auto-pause window
+    pub recent_failed_auths: Vec<u64>,
+    /// Contract whose approval is required before an added viewer takes effect
+    pub governance: Option<CanonicalAddr>,
+    /// Viewers added by the owner that still wait for governance approval
+    pub pending_viewers: Vec<CanonicalAddr>,
+    /// When set, secret variables can only be read through `CosignRead`
+    pub read_quorum: Option<ReadQuorum>,
+    /// Co-signatures collected towards the next quorum read
+    pub read_cosigns: Vec<ReadCosign>,
+    /// Secret variables written by `StageSecrets`, invisible to readers until committed
+    pub staged_secret_variables: Option<BTreeMap<String, String>>,
+    /// Viewing keys of any other length are rejected before they are hashed
+    pub viewing_key_length: u32,
+    /// Hash of the owner's manifest describing the expected secret variables
+    pub manifest_hash: Option<Binary>,
+    /// Blocks a viewer's viewing key stays valid unless the viewer has its own TTL
+    pub viewing_key_ttl_blocks: Option<u64>,
+    /// Secret keys that are deleted as soon as a viewer acknowledges reading them
+    pub read_once_keys: Vec<String>,
+    /// Read once keys that were acknowledged, asking for them again is an error
+    pub consumed_keys: Vec<String>,
+    /// Height from which a secret key can no longer be read by anyone but the owner
+    pub readable_until: BTreeMap<String, u64>,
+    /// Values viewers get instead of a key whose readable until height has been reached
+    pub expired_fallbacks: BTreeMap<String, String>,
+    /// Audited reads per viewer, viewers without an entry have not read yet
+    pub access_counts: Vec<AccessCount>,
+    /// Earlier secret variables with the height they were replaced at, oldest first
+    pub secret_variables_history: Vec<(u64, BTreeMap<String, String>)>,
+    pub max_history_len: u32,
+    pub max_keys_total: Option<u32>,
+    /// Compressed secp256k1 key whose signed vouchers let an address add itself as a viewer
+    pub voucher_pubkey: Option<Binary>,
+    /// Execute messages, by their JSON name, that are rejected for good
+    pub disabled_actions: Vec<String>,
+    /// Viewers that stay configured but lose access once their removal height is reached
+    pub scheduled_removals: Vec<ScheduledRemoval>,
+    /// Set at instantiate for good, the owner can no longer execute anything
+    pub immutable: bool,
+}
+
+/// Number of viewers that must co-sign within `window_blocks` to read the secret variables
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ReadQuorum {
+    pub quorum: u32,
+    pub window_blocks: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ReadCosign {
+    pub viewer: CanonicalAddr,
+    pub height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AccessCount {
+    pub viewer: CanonicalAddr,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct ScheduledRemoval {
+    pub viewer: CanonicalAddr,
+    pub remove_at_height: u64,
+}
+
+impl State {
+    pub fn is_allowed_viewer(&self, address: &CanonicalAddr) -> bool {
+        self.allowed_viewers.iter().any(|v| v.address == *address)
+    }
+
+    /// Allowed viewer whose scheduled removal, if any, has not been reached at `height`
+    pub fn is_active_viewer(&self, address: &CanonicalAddr, height: u64) -> bool {
+        self.is_allowed_viewer(address)
+            && !self
+                .scheduled_removals
+                .iter()
+                .any(|r| r.viewer == *address && r.remove_at_height <= height)
+    }
+}
+
+/// A viewer and the secret keys it may read
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct AllowedViewer {
+    pub address: CanonicalAddr,
+    /// Secret keys this viewer may read, `None` means every key
+    pub keys: Option<Vec<String>>,
+}
+
+/// Off-chain location of secret variables too large to keep in storage
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct SecretReference {
+    /// Where the content lives, e.g. an IPFS CID or a URL
+    pub uri: String,
+    /// Hash of the content, for integrity checks after fetching it
+    pub hash: Binary,
+}
+
+/// A SNIP-721 token whose owner may read the secret variables
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct NftGate {
+    pub contract_addr: String,
+    pub code_hash: String,
+    pub token_id: String,
+}
+
+/// Delegators of at least `min_delegation` to `validator` may read the secret variables
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct DelegationGate {
+    pub validator: String,
+    pub min_delegation: Uint128,
+}
+
+/// An oracle contract and the condition it is asked about before secret variables are returned
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct RevealOracle {
+    pub contract_addr: String,
+    pub code_hash: String,
+    pub condition: String,
 }
 
 pub fn config(storage: &mut dyn Storage) -> Singleton<State> {
@@ -20,3 +186,162 @@ pub fn config(storage: &mut dyn Storage) -> Singleton<State> {
 pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<State> {
     singleton_read(storage, CONFIG_KEY)
 }
+
+/// Failed viewing key authentications reported for a single account
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq, JsonSchema)]
+pub struct FailedAuths {
+    pub count: u32,
+    pub locked_until: Option<u64>,
+}
+
+pub fn failed_auths(storage: &mut dyn Storage) -> Bucket<FailedAuths> {
+    bucket(storage, FAILED_AUTHS_KEY)
+}
+
+pub fn failed_auths_read(storage: &dyn Storage) -> ReadonlyBucket<FailedAuths> {
+    bucket_read(storage, FAILED_AUTHS_KEY)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MembershipAction {
+    Added,
+    Removed,
+}
+
+/// One entry of the append-only log of viewer membership changes
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct MembershipChange {
+    pub viewer: CanonicalAddr,
+    pub action: MembershipAction,
+    pub height: u64,
+}
+
+/// Entries are keyed by their big-endian index, starting at zero
+pub fn membership_log(storage: &mut dyn Storage) -> Bucket<MembershipChange> {
+    bucket(storage, MEMBERSHIP_LOG_KEY)
+}
+
+pub fn membership_log_read(storage: &dyn Storage) -> ReadonlyBucket<MembershipChange> {
+    bucket_read(storage, MEMBERSHIP_LOG_KEY)
+}
+
+pub fn membership_log_len(storage: &mut dyn Storage) -> Singleton<u64> {
+    singleton(storage, MEMBERSHIP_LOG_LEN_KEY)
+}
+
+pub fn membership_log_len_read(storage: &dyn Storage) -> ReadonlySingleton<u64> {
+    singleton_read(storage, MEMBERSHIP_LOG_LEN_KEY)
+}
+
+/// Height of the most recent viewer addition, unset until the first one
+pub fn last_grant_height(storage: &mut dyn Storage) -> Singleton<u64> {
+    singleton(storage, LAST_GRANT_HEIGHT_KEY)
+}
+
+pub fn last_grant_height_read(storage: &dyn Storage) -> ReadonlySingleton<u64> {
+    singleton_read(storage, LAST_GRANT_HEIGHT_KEY)
+}
+
+/// Number of read once secrets acknowledged by any viewer, unset until the first one
+pub fn acknowledged_reads(storage: &mut dyn Storage) -> Singleton<u64> {
+    singleton(storage, ACKNOWLEDGED_READS_KEY)
+}
+
+pub fn acknowledged_reads_read(storage: &dyn Storage) -> ReadonlySingleton<u64> {
+    singleton_read(storage, ACKNOWLEDGED_READS_KEY)
+}
+
+/// Number of accounts holding a viewing key, i.e. keys generated minus keys revoked
+pub fn live_viewing_keys(storage: &mut dyn Storage) -> Singleton<u32> {
+    singleton(storage, LIVE_VIEWING_KEYS_KEY)
+}
+
+pub fn live_viewing_keys_read(storage: &dyn Storage) -> ReadonlySingleton<u32> {
+    singleton_read(storage, LIVE_VIEWING_KEYS_KEY)
+}
+
+/// Owner supplied hints about each viewer, e.g. an expected origin, keyed by canonical address.
+///
+/// They are only stored for off-chain verification and never enforced.
+pub fn viewer_metadata(storage: &mut dyn Storage) -> Bucket<String> {
+    bucket(storage, VIEWER_METADATA_KEY)
+}
+
+pub fn viewer_metadata_read(storage: &dyn Storage) -> ReadonlyBucket<String> {
+    bucket_read(storage, VIEWER_METADATA_KEY)
+}
+
+/// Viewing key TTLs in blocks of the viewers that override the contract-wide default,
+/// keyed by canonical address
+pub fn viewer_key_ttl(storage: &mut dyn Storage) -> Bucket<u64> {
+    bucket(storage, VIEWER_KEY_TTL_KEY)
+}
+
+pub fn viewer_key_ttl_read(storage: &dyn Storage) -> ReadonlyBucket<u64> {
+    bucket_read(storage, VIEWER_KEY_TTL_KEY)
+}
+
+/// Minimum entropy length in bytes of the viewers that have to supply more
+/// entropy when generating a viewing key, keyed by canonical address
+pub fn viewer_min_entropy(storage: &mut dyn Storage) -> Bucket<u32> {
+    bucket(storage, VIEWER_MIN_ENTROPY_KEY)
+}
+
+pub fn viewer_min_entropy_read(storage: &dyn Storage) -> ReadonlyBucket<u32> {
+    bucket_read(storage, VIEWER_MIN_ENTROPY_KEY)
+}
+
+/// Height from which a viewer's current viewing key is no longer accepted,
+/// keyed by canonical address. Keys generated without a TTL have no entry
+pub fn viewing_key_expiry(storage: &mut dyn Storage) -> Bucket<u64> {
+    bucket(storage, VIEWING_KEY_EXPIRY_KEY)
+}
+
+pub fn viewing_key_expiry_read(storage: &dyn Storage) -> ReadonlyBucket<u64> {
+    bucket_read(storage, VIEWING_KEY_EXPIRY_KEY)
+}
+
+/// Vouchers that were already redeemed, keyed by the hash their signature covers
+pub fn redeemed_vouchers(storage: &mut dyn Storage) -> Bucket<bool> {
+    bucket(storage, REDEEMED_VOUCHERS_KEY)
+}
+
+pub fn redeemed_vouchers_read(storage: &dyn Storage) -> ReadonlyBucket<bool> {
+    bucket_read(storage, REDEEMED_VOUCHERS_KEY)
+}
+
+/// Height of the last transaction sent by the owner, starting with the instantiation
+pub fn last_owner_activity(storage: &mut dyn Storage) -> Singleton<u64> {
+    singleton(storage, LAST_OWNER_ACTIVITY_KEY)
+}
+
+pub fn last_owner_activity_read(storage: &dyn Storage) -> ReadonlySingleton<u64> {
+    singleton_read(storage, LAST_OWNER_ACTIVITY_KEY)
+}
+
+/// Whether an account holds a viewing key it knows, keyed by canonical address
+pub fn has_key(storage: &mut dyn Storage) -> Bucket<bool> {
+    bucket(storage, HAS_KEY_KEY)
+}
+
+pub fn has_key_read(storage: &dyn Storage) -> ReadonlyBucket<bool> {
+    bucket_read(storage, HAS_KEY_KEY)
+}
+
+/// One entry of the capped feed of recent executes
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, JsonSchema)]
+pub struct RecentAction {
+    pub action: String,
+    pub actor: CanonicalAddr,
+    pub height: u64,
+}
+
+/// Oldest first, trimmed to the newest entries on every write
+pub fn recent_actions(storage: &mut dyn Storage) -> Singleton<Vec<RecentAction>> {
+    singleton(storage, RECENT_ACTIONS_KEY)
+}
+
+pub fn recent_actions_read(storage: &dyn Storage) -> ReadonlySingleton<Vec<RecentAction>> {
+    singleton_read(storage, RECENT_ACTIONS_KEY)
+}